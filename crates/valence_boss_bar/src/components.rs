@@ -1,12 +1,15 @@
 use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt;
 
-use bevy_ecs::prelude::{Bundle, Component};
+use bevy_ecs::prelude::{Bundle, Component, Entity};
 use derive_more::{Deref, DerefMut};
 use valence_entity::EntityLayerId;
 use valence_server::protocol::packets::play::boss_bar_s2c::{
     BossBarAction, BossBarColor, BossBarDivision, BossBarFlags,
 };
-use valence_server::{Text, UniqueId};
+use valence_server::text::IntoText;
+use valence_server::{BlockPos, Text, UniqueId};
 
 /// The bundle of components that make up a boss bar.
 #[derive(Bundle, Default)]
@@ -19,6 +22,150 @@ pub struct BossBarBundle {
     pub layer: EntityLayerId,
 }
 
+impl BossBarBundle {
+    /// Returns a [`BossBarBuilder`] for building a boss bar one part at a
+    /// time.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use valence_boss_bar::BossBarBundle;
+    ///
+    /// let bundle = BossBarBundle::builder().title("Boss").health(0.5).build();
+    ///
+    /// assert_eq!(bundle.health.0, 0.5);
+    /// ```
+    pub fn builder() -> BossBarBuilder {
+        BossBarBuilder::default()
+    }
+
+    /// Creates a boss bar with the given title on `layer`. Every client that
+    /// can see the layer sees the boss bar, and clients gain or lose it as the
+    /// layer is added to or removed from their [`VisibleEntityLayers`].
+    ///
+    /// The remaining fields are set to their defaults.
+    ///
+    /// [`VisibleEntityLayers`]: valence_server::client::VisibleEntityLayers
+    pub fn for_layer(layer: Entity, title: impl IntoText<'static>) -> Self {
+        Self {
+            title: BossBarTitle(title.into_text()),
+            layer: EntityLayerId(layer),
+            ..Default::default()
+        }
+    }
+
+    /// Returns this bundle together with a [`BossBarHidden`] marker, so the
+    /// boss bar can be configured before it is shown to anyone.
+    pub fn hidden(self) -> (Self, BossBarHidden) {
+        (self, BossBarHidden)
+    }
+}
+
+/// A builder for [`BossBarBundle`], created with [`BossBarBundle::builder`].
+/// Parts that aren't set keep the values from [`BossBarBundle::default`].
+#[derive(Default)]
+pub struct BossBarBuilder {
+    bundle: BossBarBundle,
+}
+
+impl BossBarBuilder {
+    pub fn title(mut self, title: impl IntoText<'static>) -> Self {
+        self.bundle.title = BossBarTitle(title.into_text());
+        self
+    }
+
+    pub fn health(mut self, health: f32) -> Self {
+        self.bundle.health = BossBarHealth(health);
+        self
+    }
+
+    pub fn color(mut self, color: BossBarColor) -> Self {
+        self.bundle.style.color = color;
+        self
+    }
+
+    pub fn division(mut self, division: BossBarDivision) -> Self {
+        self.bundle.style.division = division;
+        self
+    }
+
+    pub fn flags(mut self, flags: BossBarFlags) -> Self {
+        self.bundle.flags = flags;
+        self
+    }
+
+    /// Sets the entity layer of the boss bar. Every client that can see the
+    /// layer sees the boss bar.
+    pub fn layer(mut self, layer: Entity) -> Self {
+        self.bundle.layer = EntityLayerId(layer);
+        self
+    }
+
+    pub fn build(self) -> BossBarBundle {
+        self.bundle
+    }
+}
+
+/// Marker component for boss bars that should not be shown to clients. No
+/// packets are sent for a hidden boss bar. Removing the marker sends the full
+/// boss bar to everyone who can see it, and inserting it on a visible boss bar
+/// removes the boss bar from their screens.
+#[derive(Component, Copy, Clone, Default, Debug)]
+pub struct BossBarHidden;
+
+/// Marker component that resends a boss bar in full to everyone who can see it,
+/// even though none of its parts changed. This is useful when clients may have
+/// lost track of the boss bar, such as after a forced state resync.
+///
+/// The boss bar is sent with a single `Add`, which replaces it in place. The
+/// marker removes itself afterwards. Hidden boss bars send nothing.
+#[derive(Component, Copy, Clone, Default, Debug)]
+pub struct BossBarResync;
+
+/// The number of ticks a boss bar has left before it is despawned. A boss bar
+/// spawned with a lifetime of `n` is shown for exactly `n` ticks, including
+/// the tick it is spawned on. Boss bars are removed from clients' screens when
+/// they despawn.
+#[derive(Component, Copy, Clone, PartialEq, Eq, Default, Debug, Deref, DerefMut)]
+pub struct BossBarLifetime(pub u32);
+
+/// Defers removing a boss bar with a [`Position`] from a client's screen by
+/// `ticks` when the client moves out of range. If the client comes back in
+/// range within that window, the boss bar is never removed. This avoids
+/// flicker when a client moves back and forth along the edge of the range.
+///
+/// Updates to the boss bar are not sent to clients out of range. When a
+/// client comes back, the boss bar is sent in full, which replaces it in
+/// place without flicker. A boss bar that is despawned, hidden or moved to
+/// another layer during the window is removed when the window ends.
+///
+/// [`Position`]: valence_entity::Position
+#[derive(Component, Copy, Clone, PartialEq, Eq, Default, Debug)]
+pub struct BossBarViewerGrace {
+    pub ticks: u32,
+}
+
+/// Makes a boss bar mirror the title, health, style and flags of another boss
+/// bar. The mirror keeps its own [`UniqueId`] and [`EntityLayerId`], so the
+/// same boss bar can be shown on several layers at once.
+///
+/// Mirroring a boss bar that is itself a mirror is not supported.
+#[derive(Component, Copy, Clone, PartialEq, Eq, Debug)]
+pub struct BossBarMirror(pub Entity);
+
+/// Makes a boss bar with a [`Position`] follow another entity. The followed
+/// entity's position is copied to the boss bar every tick, so the boss bar is
+/// shown to the clients that can see the chunk the entity is in.
+///
+/// The boss bar is despawned when the followed entity is. A boss bar can
+/// follow another boss bar that is itself following an entity, in which case
+/// both end up at that entity's position. Boss bars that follow each other in
+/// a cycle are left where they are.
+///
+/// [`Position`]: valence_entity::Position
+#[derive(Component, Copy, Clone, PartialEq, Eq, Debug)]
+pub struct BossBarFollow(pub Entity);
+
 /// The title of a boss bar.
 #[derive(Component, Clone, Default, Deref, DerefMut)]
 pub struct BossBarTitle(pub Text);
@@ -29,24 +176,265 @@ impl ToPacketAction for BossBarTitle {
     }
 }
 
-/// The health of a boss bar.
+/// Progressively reveals a boss bar's title, `chars_per_tick` characters at a
+/// time, like a typewriter. The component removes itself once the full title
+/// is shown.
+///
+/// Characters are counted as they are rendered, so formatting is kept intact
+/// and only the text itself is cut short. Content that is resolved by the
+/// client, such as translations and keybinds, counts as a single character.
+///
+/// Every step changes the [`BossBarTitle`], which costs one `UpdateTitle`
+/// packet per tick for every client that can see the boss bar.
+#[derive(Component, Clone, Default)]
+pub struct BossBarTitleReveal {
+    pub full: Text,
+    pub chars_per_tick: usize,
+    /// The number of characters revealed so far.
+    pub revealed: usize,
+}
+
+impl BossBarTitleReveal {
+    pub fn new(full: Text, chars_per_tick: usize) -> Self {
+        Self {
+            full,
+            chars_per_tick,
+            revealed: 0,
+        }
+    }
+}
+
+/// Scrolls `text` through a boss bar's title like a marquee, showing `width`
+/// characters at a time and moving by one character every `interval` ticks.
+/// The text wraps around, so a trailing space or separator keeps its end apart
+/// from its start. Text that fits in `width` characters is shown as is.
+///
+/// Characters are counted as `char`s, so multi-byte characters are never split.
+/// The title keeps its own formatting, such as its color, but its extra
+/// components are replaced by the visible window.
+///
+/// The [`BossBarTitle`] is only changed when the visible window changes, so a
+/// title is sent at most once every `interval` ticks.
+#[derive(Component, Clone, Default, Debug)]
+pub struct BossBarScroll {
+    pub text: String,
+    pub width: usize,
+    pub interval: u32,
+    /// The index of the first visible character.
+    pub offset: usize,
+    /// The number of ticks since the window last moved.
+    pub elapsed: u32,
+}
+
+impl BossBarScroll {
+    pub fn new(text: impl Into<String>, width: usize, interval: u32) -> Self {
+        Self {
+            text: text.into(),
+            width,
+            interval,
+            offset: 0,
+            elapsed: 0,
+        }
+    }
+
+    /// Returns the currently visible part of the text.
+    pub fn window(&self) -> String {
+        let len = self.text.chars().count();
+
+        if len <= self.width {
+            return self.text.clone();
+        }
+
+        self.text
+            .chars()
+            .cycle()
+            .skip(self.offset % len)
+            .take(self.width)
+            .collect()
+    }
+}
+
+/// A secondary line of text for a boss bar, such as a timer or an objective.
+///
+/// Boss bars have no such text in the vanilla protocol. This is a Valence-side
+/// convenience that shows the text in the action bar of every client that can
+/// see the boss bar whenever it changes. It is also shown to clients when the
+/// boss bar is revealed, or when they start seeing it because its layer became
+/// visible to them or it came into their view. The client fades the action bar
+/// out after a few seconds, so long-lived text should be updated periodically.
+#[derive(Component, Clone, PartialEq, Default, Debug, Deref, DerefMut)]
+pub struct BossBarOverlay(pub Text);
+
+/// The health of a boss bar, in the range `0.0..=1.0`.
+///
+/// Prefer [`BossBarHealth::set`] over writing to the field directly. Values
+/// written directly are still sanitized with [`BossBarHealth::get`] before
+/// they are sent to clients.
 #[derive(Component, Default, Deref, DerefMut)]
 pub struct BossBarHealth(pub f32);
 
+impl BossBarHealth {
+    /// Creates a health value from a percentage in the range `0.0..=100.0`.
+    /// Percentages outside this range are clamped, and NaN becomes `0.0`.
+    pub fn from_percent(percent: f32) -> Self {
+        if percent.is_nan() {
+            Self(0.0)
+        } else {
+            Self(percent.clamp(0.0, 100.0) / 100.0)
+        }
+    }
+
+    /// Returns the health as a percentage, where `1.0` is `100.0`.
+    pub fn percent(&self) -> f32 {
+        self.0 * 100.0
+    }
+
+    /// Returns the health clamped to `0.0..=1.0`. NaN is returned as `0.0`.
+    pub fn get(&self) -> f32 {
+        if self.0.is_nan() {
+            0.0
+        } else {
+            self.0.clamp(0.0, 1.0)
+        }
+    }
+
+    /// Sets the health, clamping it to `0.0..=1.0`. If `health` is NaN, the
+    /// previous health is kept.
+    pub fn set(&mut self, health: f32) {
+        if !health.is_nan() {
+            self.0 = health.clamp(0.0, 1.0);
+        }
+    }
+
+    /// Sets the health if it is in the range `0.0..=1.0`. Otherwise, the
+    /// health is left unchanged and an error is returned.
+    pub fn try_set(&mut self, health: f32) -> Result<(), BossBarHealthError> {
+        if health.is_nan() {
+            Err(BossBarHealthError::NaN)
+        } else if !(0.0..=1.0).contains(&health) {
+            Err(BossBarHealthError::OutOfRange(health))
+        } else {
+            self.0 = health;
+            Ok(())
+        }
+    }
+}
+
 impl ToPacketAction for BossBarHealth {
     fn to_packet_action(&self) -> BossBarAction {
-        BossBarAction::UpdateHealth(self.0)
+        BossBarAction::UpdateHealth(self.get())
     }
 }
 
+/// The error returned by [`BossBarHealth::try_set`].
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum BossBarHealthError {
+    /// The health is NaN.
+    NaN,
+    /// The health is outside the range `0.0..=1.0`.
+    OutOfRange(f32),
+}
+
+impl fmt::Display for BossBarHealthError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BossBarHealthError::NaN => write!(f, "boss bar health is NaN"),
+            BossBarHealthError::OutOfRange(health) => {
+                write!(f, "boss bar health {health} is outside the range 0.0..=1.0")
+            }
+        }
+    }
+}
+
+impl std::error::Error for BossBarHealthError {}
+
+/// Overrides the [`BossBarHealth`] of a boss bar for individual clients, so
+/// each client can track its own progress on a shared boss bar. Clients not in
+/// the map see the shared health.
+///
+/// Boss bars without overrides are sent to their viewers through their entity
+/// layer, which encodes each packet once for all viewers. Once the map is not
+/// empty, spawning, revealing and health updates of the boss bar are instead
+/// written to every viewing client separately, which encodes one packet per
+/// viewer. If most viewers need their own health, consider giving each of
+/// them their own boss bar instead.
+#[derive(Component, Clone, PartialEq, Default, Debug, Deref, DerefMut)]
+pub struct BossBarPerViewer(pub HashMap<Entity, f32>);
+
+impl BossBarPerViewer {
+    /// Returns the health `client` sees, clamped like [`BossBarHealth::get`].
+    pub fn health_for(&self, client: Entity, health: &BossBarHealth) -> f32 {
+        match self.0.get(&client) {
+            Some(&health) => BossBarHealth(health).get(),
+            None => health.get(),
+        }
+    }
+}
+
+/// Health values of a boss bar at which a [`BossBarHealthThresholdCrossed`]
+/// event is sent whenever the health crosses them, in either direction.
+///
+/// Every threshold keeps its own hysteresis and sends its own events. A
+/// threshold only counts as crossed once the health is more than
+/// [`BOSS_BAR_THRESHOLD_DEBOUNCE`] past it. Thresholds closer together than
+/// that don't affect each other.
+///
+/// A single change that crosses several thresholds sends one event for each,
+/// in the order the health passed them. Falling health sends the highest
+/// threshold first, and rising health the lowest.
+///
+/// [`BossBarHealthThresholdCrossed`]: crate::BossBarHealthThresholdCrossed
+/// [`BOSS_BAR_THRESHOLD_DEBOUNCE`]: crate::BOSS_BAR_THRESHOLD_DEBOUNCE
+#[derive(Component, Clone, PartialEq, Default, Debug, Deref, DerefMut)]
+pub struct BossBarThresholds(pub Vec<f32>);
+
+/// Sets the [`BossBarHealth`] of a boss bar to how far the `tracked` entity
+/// has travelled from `from` to `to`, turning the boss bar into a journey
+/// indicator.
+///
+/// Progress is the tracked entity's [`Position`] projected onto the line from
+/// `from` to `to`, clamped to `0.0..=1.0`. If the tracked entity no longer
+/// exists, the health stays where it was.
+///
+/// [`Position`]: valence_entity::Position
+#[derive(Component, Copy, Clone, PartialEq, Eq, Debug)]
+pub struct BossBarDistanceProgress {
+    pub from: BlockPos,
+    pub to: BlockPos,
+    pub tracked: Entity,
+}
+
 /// The style of a boss bar. This includes the color and division of the boss
 /// bar.
-#[derive(Component, Default)]
+#[derive(Component, Copy, Clone, PartialEq, Eq, Default, Debug)]
 pub struct BossBarStyle {
     pub color: BossBarColor,
     pub division: BossBarDivision,
 }
 
+impl BossBarStyle {
+    /// Returns one of a handful of predefined styles by name, or `None` if
+    /// there is no preset with that name.
+    ///
+    /// - `"health"`: red with twelve notches.
+    /// - `"mana"`: blue with no division.
+    /// - `"experience"`: green with twenty notches.
+    /// - `"timer"`: yellow with ten notches.
+    /// - `"boss"`: purple with six notches.
+    pub fn preset(name: &str) -> Option<Self> {
+        let (color, division) = match name {
+            "health" => (BossBarColor::Red, BossBarDivision::TwelveNotches),
+            "mana" => (BossBarColor::Blue, BossBarDivision::NoDivision),
+            "experience" => (BossBarColor::Green, BossBarDivision::TwentyNotches),
+            "timer" => (BossBarColor::Yellow, BossBarDivision::TenNotches),
+            "boss" => (BossBarColor::Purple, BossBarDivision::SixNotches),
+            _ => return None,
+        };
+
+        Some(Self { color, division })
+    }
+}
+
 impl ToPacketAction for BossBarStyle {
     fn to_packet_action(&self) -> BossBarAction {
         BossBarAction::UpdateStyle(self.color, self.division)
@@ -59,6 +447,77 @@ impl ToPacketAction for BossBarFlags {
     }
 }
 
+/// The parts of a boss bar needed to show it in full, borrowed from its
+/// components.
+///
+/// # Examples
+///
+/// ```
+/// use valence_boss_bar::{
+///     BossBarAction, BossBarFlags, BossBarHealth, BossBarSnapshot, BossBarStyle, BossBarTitle,
+/// };
+///
+/// let title = BossBarTitle::default();
+/// let style = BossBarStyle::default();
+/// let flags = BossBarFlags::default();
+///
+/// let snapshot = BossBarSnapshot::new(&title, &BossBarHealth(0.5), &style, &flags);
+///
+/// assert!(matches!(
+///     snapshot.add_action(),
+///     BossBarAction::Add { health, .. } if health == 0.5
+/// ));
+/// ```
+#[derive(Copy, Clone)]
+pub struct BossBarSnapshot<'a> {
+    pub title: &'a BossBarTitle,
+    /// The health to show, already sanitized and, for boss bars with
+    /// [`BossBarPerViewer`] overrides, resolved for the client being sent to.
+    pub health: f32,
+    pub style: &'a BossBarStyle,
+    pub flags: &'a BossBarFlags,
+}
+
+impl<'a> BossBarSnapshot<'a> {
+    pub fn new(
+        title: &'a BossBarTitle,
+        health: &BossBarHealth,
+        style: &'a BossBarStyle,
+        flags: &'a BossBarFlags,
+    ) -> Self {
+        Self {
+            title,
+            health: health.get(),
+            style,
+            flags,
+        }
+    }
+
+    /// Returns the snapshot with `health` shown instead, such as the health
+    /// from [`BossBarPerViewer::health_for`].
+    pub fn with_health(self, health: f32) -> Self {
+        Self { health, ..self }
+    }
+
+    /// Returns the `Add` action that shows the whole boss bar at once. Sending
+    /// it for a boss bar the client already has replaces it in place.
+    pub fn add_action(self) -> BossBarAction<'a> {
+        BossBarAction::Add {
+            title: Cow::Borrowed(&self.title.0),
+            health: self.health,
+            color: self.style.color,
+            division: self.style.division,
+            flags: *self.flags,
+        }
+    }
+}
+
+impl ToPacketAction for BossBarSnapshot<'_> {
+    fn to_packet_action(&self) -> BossBarAction {
+        self.add_action()
+    }
+}
+
 /// Trait for converting a component to a boss bar action.
 pub(crate) trait ToPacketAction {
     fn to_packet_action(&self) -> BossBarAction;
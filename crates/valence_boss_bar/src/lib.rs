@@ -18,20 +18,26 @@
     clippy::dbg_macro
 )]
 
-use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
 
 use bevy_app::prelude::*;
 use bevy_ecs::prelude::*;
+use bevy_ecs::system::{Command, SystemParam};
 use valence_server::client::{
     Client, OldViewDistance, OldVisibleEntityLayers, ViewDistance, VisibleEntityLayers,
 };
 use valence_server::layer::UpdateLayersPreClientSet;
+use valence_server::math::DVec3;
 pub use valence_server::protocol::packets::play::boss_bar_s2c::{
-    BossBarAction, BossBarColor, BossBarDivision, BossBarFlags,
+    BossBarAction, BossBarColor, BossBarDivision, BossBarFlag, BossBarFlags,
+    ParseBossBarColorError, ParseBossBarDivisionError,
 };
 use valence_server::protocol::packets::play::BossBarS2c;
 use valence_server::protocol::WritePacket;
-use valence_server::{ChunkView, Despawned, EntityLayer, Layer, UniqueId};
+use valence_server::text::{IntoText, TextContent};
+use valence_server::title::SetTitle;
+use valence_server::uuid::Uuid;
+use valence_server::{ChunkPos, ChunkView, Despawned, EntityLayer, Layer, Server, Text, UniqueId};
 
 mod components;
 pub use components::*;
@@ -41,27 +47,966 @@ pub struct BossBarPlugin;
 
 impl Plugin for BossBarPlugin {
     fn build(&self, app: &mut bevy_app::App) {
-        app.add_systems(
+        app.configure_sets(
             PostUpdate,
-            (
-                update_boss_bar::<BossBarTitle>,
-                update_boss_bar::<BossBarHealth>,
-                update_boss_bar::<BossBarStyle>,
-                update_boss_bar::<BossBarFlags>,
-                update_boss_bar_layer_view,
-                update_boss_bar_chunk_view,
-                boss_bar_despawn,
-            )
+            (BossBarSet::Update, BossBarSet::Dispatch)
+                .chain()
                 .before(UpdateLayersPreClientSet),
+        )
+        .add_event::<BossBarHealthThresholdCrossed>()
+        .add_event::<BossBarViewerAdded>()
+        .add_event::<BossBarViewerRemoved>()
+        .add_systems(
+            Update,
+            (
+                update_boss_bar_lifetime,
+                despawn_announcement_layers,
+                update_boss_bar_follow,
+                update_boss_bar_title_reveal,
+                update_boss_bar_scroll,
+            ),
+        )
+        .add_systems(
+            PostUpdate,
+            (
+                (
+                    update_boss_bar_mirror,
+                    despawn_orphaned_boss_bars,
+                    update_boss_bar_distance_progress,
+                    emit_boss_bar_thresholds_crossed,
+                )
+                    .chain()
+                    .in_set(BossBarSet::Update),
+                (
+                    boss_bar_spawn,
+                    update_boss_bar_coalesced,
+                    update_boss_bar_title,
+                    update_boss_bar_overlay,
+                    update_boss_bar_health,
+                    update_boss_bar::<BossBarStyle>,
+                    update_boss_bar::<BossBarFlags>,
+                    update_boss_bar_resync,
+                    update_boss_bar_layer_view,
+                    update_boss_bar_chunk_view,
+                    update_boss_bar_hidden,
+                    boss_bar_despawn,
+                )
+                    .chain()
+                    .in_set(BossBarSet::Dispatch),
+            ),
+        );
+    }
+}
+
+/// The sets the boss bar systems in [`PostUpdate`] run in. [`Update`] runs
+/// before [`Dispatch`], and both run before the packets are written to the
+/// clients' layers.
+///
+/// [`Update`]: BossBarSet::Update
+/// [`Dispatch`]: BossBarSet::Dispatch
+#[derive(SystemSet, Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum BossBarSet {
+    /// Computes the state of boss bars, such as mirrored parts, distance
+    /// progress and crossed health thresholds.
+    Update,
+    /// Sends boss bar packets to the clients that can see them.
+    Dispatch,
+}
+
+/// Sent when the health of a boss bar with [`BossBarThresholds`] crosses one
+/// of its thresholds.
+#[derive(Event, Copy, Clone, PartialEq, Debug)]
+pub struct BossBarHealthThresholdCrossed {
+    pub boss_bar: Entity,
+    pub threshold: f32,
+    /// `true` if the health rose above the threshold, `false` if it fell below.
+    pub rising: bool,
+}
+
+/// Sent when a client starts seeing a boss bar because the boss bar was
+/// spawned or revealed, the boss bar's layer was added to the client's
+/// [`VisibleEntityLayers`], or the boss bar came into the client's view.
+#[derive(Event, Copy, Clone, PartialEq, Eq, Debug)]
+pub struct BossBarViewerAdded {
+    pub boss_bar: Entity,
+    pub client: Entity,
+}
+
+/// Sent when a client stops seeing a boss bar because the boss bar or its
+/// layer was despawned, the boss bar was hidden, the boss bar's layer was
+/// removed from the client's [`VisibleEntityLayers`], or the boss bar left the
+/// client's view.
+#[derive(Event, Copy, Clone, PartialEq, Eq, Debug)]
+pub struct BossBarViewerRemoved {
+    pub boss_bar: Entity,
+    pub client: Entity,
+}
+
+/// How far past a threshold the health of a boss bar must go before the
+/// threshold counts as crossed. This keeps small oscillations around a
+/// threshold from sending a stream of events.
+pub const BOSS_BAR_THRESHOLD_DEBOUNCE: f32 = 0.01;
+
+/// A [`SystemParam`] for finding out how many clients can see boss bars,
+/// for example to report on them in an admin command.
+///
+/// A client can see a boss bar if the boss bar's layer is in the client's
+/// [`VisibleEntityLayers`] and, for a boss bar with a [`Position`], the
+/// position is in the client's view. Hidden boss bars have no viewers.
+#[derive(SystemParam)]
+pub struct BossBarViews<'w, 's> {
+    boss_bars_query: Query<
+        'w,
+        's,
+        (&'static EntityLayerId, Option<&'static Position>),
+        (
+            With<BossBarTitle>,
+            Without<BossBarHidden>,
+            Without<Despawned>,
+        ),
+    >,
+    clients_query: Query<
+        'w,
+        's,
+        (
+            &'static VisibleEntityLayers,
+            &'static Position,
+            &'static ViewDistance,
+        ),
+        With<Client>,
+    >,
+}
+
+impl BossBarViews<'_, '_> {
+    /// Returns the number of clients that can see `boss_bar`, or `0` if it
+    /// isn't a visible boss bar.
+    pub fn count_viewers(&self, boss_bar: Entity) -> usize {
+        match self.boss_bars_query.get(boss_bar) {
+            Ok((layer, position)) => self.count(layer, position),
+            Err(_) => 0,
+        }
+    }
+
+    /// Returns the number of clients that can see each boss bar, summed over
+    /// all boss bars. This is the number of clients every boss bar update is
+    /// sent to, if every boss bar is updated.
+    pub fn total_boss_bar_views(&self) -> usize {
+        self.boss_bars_query
+            .iter()
+            .map(|(layer, position)| self.count(layer, position))
+            .sum()
+    }
+
+    fn count(&self, layer: &EntityLayerId, position: Option<&Position>) -> usize {
+        self.clients_query
+            .iter()
+            .filter(|(visible_entity_layers, client_position, view_distance)| {
+                let view = ChunkView::new(client_position.0.into(), view_distance.get());
+                can_see(visible_entity_layers, view, layer, position)
+            })
+            .count()
+    }
+}
+
+/// A [`Command`] that shows a temporary boss bar to every connected client.
+///
+/// A single boss bar is spawned on a new [`EntityLayer`], which is added to
+/// every client's [`VisibleEntityLayers`]. Each client therefore sees the boss
+/// bar exactly once, no matter how many other layers it can see. Clients that
+/// join afterwards don't see it.
+///
+/// The boss bar despawns automatically after `duration` ticks, which removes it
+/// from the clients' screens. Its layer is removed from the clients and
+/// despawned on the following tick.
+///
+/// Every announcement spawns its own [`EntityLayer`], with its own packet
+/// buffers, and every client tracks it until it is despawned. This is cheap
+/// for the occasional announcement, but many concurrent announcements are
+/// better served by boss bars on a shared layer.
+///
+/// Usually queued with [`BossBarCommandsExt::announce_boss_bar`].
+#[derive(Clone, PartialEq, Debug)]
+pub struct AnnounceBossBar {
+    pub title: Text,
+    pub color: BossBarColor,
+    /// The number of ticks the boss bar is shown for.
+    pub duration: u32,
+}
+
+impl Command for AnnounceBossBar {
+    fn apply(self, world: &mut World) {
+        let layer = EntityLayer::new(world.resource::<Server>());
+        let layer = world.spawn((layer, AnnouncementLayer)).id();
+
+        world.spawn((
+            BossBarBundle {
+                title: BossBarTitle(self.title),
+                health: BossBarHealth(1.0),
+                style: BossBarStyle {
+                    color: self.color,
+                    division: BossBarDivision::default(),
+                },
+                layer: EntityLayerId(layer),
+                ..Default::default()
+            },
+            BossBarLifetime(self.duration),
+        ));
+
+        let mut clients_query = world.query_filtered::<&mut VisibleEntityLayers, With<Client>>();
+        for mut visible_entity_layers in clients_query.iter_mut(world) {
+            visible_entity_layers.0.insert(layer);
+        }
+    }
+}
+
+/// Extension methods for queueing boss bar [`Command`]s.
+pub trait BossBarCommandsExt {
+    /// Shows a temporary boss bar to every connected client for `duration`
+    /// ticks. See [`AnnounceBossBar`].
+    fn announce_boss_bar(
+        &mut self,
+        title: impl IntoText<'static>,
+        color: BossBarColor,
+        duration: u32,
+    );
+}
+
+impl BossBarCommandsExt for Commands<'_, '_> {
+    fn announce_boss_bar(
+        &mut self,
+        title: impl IntoText<'static>,
+        color: BossBarColor,
+        duration: u32,
+    ) {
+        self.add(AnnounceBossBar {
+            title: title.into_text(),
+            color,
+            duration,
+        });
+    }
+}
+
+/// Marker component for the entity layers spawned by [`AnnounceBossBar`].
+#[derive(Component)]
+struct AnnouncementLayer;
+
+/// Removes the layers of [`AnnounceBossBar`]s whose boss bar has despawned
+/// from the clients, and despawns them.
+fn despawn_announcement_layers(
+    layers_query: Query<Entity, (With<AnnouncementLayer>, Without<Despawned>)>,
+    boss_bars_query: Query<&EntityLayerId, With<BossBarTitle>>,
+    mut clients_query: Query<&mut VisibleEntityLayers, With<Client>>,
+    mut commands: Commands,
+) {
+    for layer in &layers_query {
+        if boss_bars_query.iter().any(|layer_id| layer_id.0 == layer) {
+            continue;
+        }
+
+        for mut visible_entity_layers in &mut clients_query {
+            if visible_entity_layers.0.contains(&layer) {
+                visible_entity_layers.0.remove(&layer);
+            }
+        }
+
+        commands.entity(layer).insert(Despawned);
+    }
+}
+
+/// Sends the full boss bar to its viewers when it is spawned.
+fn boss_bar_spawn(
+    boss_bars_query: Query<
+        (
+            Entity,
+            &UniqueId,
+            &BossBarTitle,
+            &BossBarHealth,
+            &BossBarStyle,
+            &BossBarFlags,
+            &EntityLayerId,
+            Option<&Position>,
+            Option<&BossBarPerViewer>,
+        ),
+        (Added<UniqueId>, Without<BossBarHidden>),
+    >,
+    mut entity_layers_query: Query<&mut EntityLayer>,
+    mut clients_query: Query<ViewerQueryData>,
+    mut viewer_added_events: EventWriter<BossBarViewerAdded>,
+) {
+    for (boss_bar, id, title, health, style, flags, entity_layer_id, pos, per_viewer) in
+        boss_bars_query.iter()
+    {
+        for_each_viewer_entity(&clients_query, entity_layer_id, pos, |client| {
+            viewer_added_events.send(BossBarViewerAdded { boss_bar, client });
+        });
+
+        let snapshot = BossBarSnapshot::new(title, health, style, flags);
+        let add_packet = |health| BossBarS2c {
+            id: id.0,
+            action: snapshot.with_health(health).add_action(),
+        };
+
+        if let Some(per_viewer) = per_viewer.filter(|p| !p.is_empty()) {
+            for_each_viewer(
+                &mut clients_query,
+                entity_layer_id,
+                pos,
+                |client, writer| {
+                    writer.write_packet(&add_packet(per_viewer.health_for(client, health)));
+                },
+            );
+        } else if let Ok(mut entity_layer) = entity_layers_query.get_mut(entity_layer_id.0) {
+            let packet = add_packet(health.get());
+            if let Some(pos) = pos {
+                entity_layer.view_writer(pos.0).write_packet(&packet);
+            } else {
+                entity_layer.write_packet(&packet);
+            }
+        }
+    }
+}
+
+/// The clients that boss bars with [`BossBarPerViewer`] overrides are written
+/// to one by one.
+type ViewerQueryData = (
+    Entity,
+    &'static mut Client,
+    &'static VisibleEntityLayers,
+    &'static Position,
+    &'static ViewDistance,
+);
+
+/// Returns whether a client can see a boss bar on `layer`, which is at
+/// `position` if it has one.
+fn can_see(
+    visible_entity_layers: &VisibleEntityLayers,
+    view: ChunkView,
+    layer: &EntityLayerId,
+    position: Option<&Position>,
+) -> bool {
+    if !visible_entity_layers.0.contains(&layer.0) {
+        return false;
+    }
+
+    match position {
+        Some(position) => view.contains(position.0.into()),
+        None => true,
+    }
+}
+
+/// Calls `f` with every client that can see a boss bar on `layer`, which is at
+/// `position` if it has one.
+fn for_each_viewer(
+    clients_query: &mut Query<ViewerQueryData>,
+    layer: &EntityLayerId,
+    position: Option<&Position>,
+    mut f: impl FnMut(Entity, &mut Client),
+) {
+    for (client_entity, mut client, visible_entity_layers, client_position, view_distance) in
+        clients_query.iter_mut()
+    {
+        let view = ChunkView::new(client_position.0.into(), view_distance.get());
+        if can_see(visible_entity_layers, view, layer, position) {
+            f(client_entity, &mut client);
+        }
+    }
+}
+
+/// Like [`for_each_viewer`], but only needs read access to the clients. This
+/// is used to find out who a packet written to the boss bar's layer reaches.
+fn for_each_viewer_entity(
+    clients_query: &Query<ViewerQueryData>,
+    layer: &EntityLayerId,
+    position: Option<&Position>,
+    mut f: impl FnMut(Entity),
+) {
+    for (client_entity, _, visible_entity_layers, client_position, view_distance) in
+        clients_query.iter()
+    {
+        let view = ChunkView::new(client_position.0.into(), view_distance.get());
+        if can_see(visible_entity_layers, view, layer, position) {
+            f(client_entity);
+        }
+    }
+}
+
+fn update_boss_bar_lifetime(
+    mut boss_bars_query: Query<(Entity, &mut BossBarLifetime), Without<Despawned>>,
+    mut commands: Commands,
+) {
+    // The tick the boss bar is spawned on counts as the first tick it is shown
+    // for, so it is despawned once the counter has already reached zero.
+    for (entity, mut lifetime) in &mut boss_bars_query {
+        if lifetime.0 == 0 {
+            commands.entity(entity).insert(Despawned);
+        } else {
+            lifetime.0 -= 1;
+        }
+    }
+}
+
+fn update_boss_bar_follow(
+    follows_query: Query<(Entity, &BossBarFollow), Without<Despawned>>,
+    mut positions: ParamSet<(
+        Query<&Position, Without<Despawned>>,
+        Query<&mut Position, With<BossBarFollow>>,
+    )>,
+    mut commands: Commands,
+) {
+    let follows: HashMap<Entity, Entity> = follows_query
+        .iter()
+        .map(|(entity, follow)| (entity, follow.0))
+        .collect();
+
+    let mut targets = vec![];
+
+    for (&entity, &followed) in &follows {
+        // Following the chain to its end keeps a boss bar that follows another
+        // boss bar up to date in the same tick. Cycles are left in place.
+        let Some(target) = follow_chain_end(&follows, followed) else {
+            continue;
+        };
+
+        match positions.p0().get(target) {
+            Ok(target_position) => targets.push((entity, target_position.0)),
+            Err(_) => {
+                commands.entity(entity).insert(Despawned);
+            }
+        }
+    }
+
+    let mut positions_query = positions.p1();
+    for (entity, target_position) in targets {
+        if let Ok(mut position) = positions_query.get_mut(entity) {
+            if position.0 != target_position {
+                position.0 = target_position;
+            }
+        }
+    }
+}
+
+/// Returns the first entity in the chain starting at `entity` that doesn't
+/// follow anything, or `None` if the chain loops.
+fn follow_chain_end(follows: &HashMap<Entity, Entity>, mut entity: Entity) -> Option<Entity> {
+    for _ in 0..=follows.len() {
+        match follows.get(&entity) {
+            Some(&next) => entity = next,
+            None => return Some(entity),
+        }
+    }
+
+    None
+}
+
+fn update_boss_bar_title_reveal(
+    mut boss_bars_query: Query<(Entity, &mut BossBarTitle, &mut BossBarTitleReveal)>,
+    mut commands: Commands,
+) {
+    for (entity, mut title, mut reveal) in &mut boss_bars_query {
+        reveal.revealed = reveal.revealed.saturating_add(reveal.chars_per_tick.max(1));
+
+        if reveal.revealed >= rendered_len(&reveal.full) {
+            title.0 = reveal.full.clone();
+            commands.entity(entity).remove::<BossBarTitleReveal>();
+        } else {
+            let mut remaining = reveal.revealed;
+            title.0 = text_prefix(&reveal.full, &mut remaining);
+        }
+    }
+}
+
+fn update_boss_bar_scroll(mut boss_bars_query: Query<(&mut BossBarTitle, &mut BossBarScroll)>) {
+    for (mut title, mut scroll) in &mut boss_bars_query {
+        if scroll.elapsed >= scroll.interval.max(1) {
+            scroll.elapsed = 0;
+
+            let len = scroll.text.chars().count().max(1);
+            scroll.offset = (scroll.offset + 1) % len;
+        }
+
+        scroll.elapsed += 1;
+
+        let mut new_title = title.0.clone();
+        new_title.content = TextContent::Text {
+            text: scroll.window().into(),
+        };
+        new_title.extra.clear();
+
+        if title.0 != new_title {
+            title.0 = new_title;
+        }
+    }
+}
+
+/// Returns the number of characters in `text` as counted by
+/// [`BossBarTitleReveal`].
+fn rendered_len(text: &Text) -> usize {
+    let len = match &text.content {
+        TextContent::Text { text } => text.chars().count(),
+        _ => 1,
+    };
+
+    len + text.extra.iter().map(rendered_len).sum::<usize>()
+}
+
+/// Returns a copy of `text` cut down to its first `remaining` characters,
+/// keeping the formatting of everything that is left.
+fn text_prefix(text: &Text, remaining: &mut usize) -> Text {
+    let mut prefix = text.clone();
+    prefix.extra.clear();
+
+    match &mut prefix.content {
+        TextContent::Text { text } => {
+            let len = text.chars().count();
+
+            if len > *remaining {
+                *text = text.chars().take(*remaining).collect::<String>().into();
+                *remaining = 0;
+            } else {
+                *remaining -= len;
+            }
+        }
+        content => {
+            if *remaining == 0 {
+                *content = TextContent::Text { text: "".into() };
+            } else {
+                *remaining -= 1;
+            }
+        }
+    }
+
+    for child in &text.extra {
+        if *remaining == 0 {
+            break;
+        }
+
+        prefix.extra.push(text_prefix(child, remaining));
+    }
+
+    prefix
+}
+
+/// Despawns boss bars whose entity layer is being or has been despawned, and
+/// removes them from the screens of clients that could see them.
+///
+/// While the layer still exists, the removal is written to the layer like any
+/// other boss bar packet. Once the layer is gone, it is sent directly to every
+/// client that still has the layer in its [`VisibleEntityLayers`].
+///
+/// Only layers that actually lose their [`EntityLayer`] count. Boss bars whose
+/// layer doesn't exist yet, such as boss bars built without a layer, are left
+/// alone.
+///
+/// This also handles boss bars that were despawned along with their layer, so
+/// that [`boss_bar_despawn`] can skip every boss bar on a despawned layer.
+/// Otherwise, whether a boss bar despawned here is removed twice would depend
+/// on when the command inserting [`Despawned`] is applied.
+fn despawn_orphaned_boss_bars(
+    boss_bars_query: Query<
+        (
+            Entity,
+            &UniqueId,
+            &EntityLayerId,
+            Option<&Position>,
+            Has<BossBarHidden>,
+            Has<Despawned>,
+        ),
+        With<BossBarTitle>,
+    >,
+    mut entity_layers_query: Query<(&mut EntityLayer, Has<Despawned>)>,
+    mut removed_layers: RemovedComponents<EntityLayer>,
+    mut clients_query: Query<ViewerQueryData>,
+    mut viewer_removed_events: EventWriter<BossBarViewerRemoved>,
+    mut commands: Commands,
+) {
+    let removed_layers: HashSet<Entity> = removed_layers.read().collect();
+
+    for (entity, id, entity_layer_id, boss_bar_position, hidden, despawned) in &boss_bars_query {
+        let packet = BossBarS2c {
+            id: id.0,
+            action: BossBarAction::Remove,
+        };
+
+        match entity_layers_query.get_mut(entity_layer_id.0) {
+            Ok((_, false)) => continue,
+            Ok((mut entity_layer, true)) => {
+                if !hidden {
+                    if let Some(pos) = boss_bar_position {
+                        entity_layer.view_writer(pos.0).write_packet(&packet);
+                    } else {
+                        entity_layer.write_packet(&packet);
+                    }
+
+                    for_each_viewer_entity(
+                        &clients_query,
+                        entity_layer_id,
+                        boss_bar_position,
+                        |client| {
+                            viewer_removed_events.send(BossBarViewerRemoved {
+                                boss_bar: entity,
+                                client,
+                            });
+                        },
+                    );
+                }
+            }
+            Err(_) if !removed_layers.contains(&entity_layer_id.0) => continue,
+            Err(_) => {
+                if !hidden {
+                    for_each_viewer(
+                        &mut clients_query,
+                        entity_layer_id,
+                        boss_bar_position,
+                        |client, writer| {
+                            writer.write_packet(&packet);
+                            viewer_removed_events.send(BossBarViewerRemoved {
+                                boss_bar: entity,
+                                client,
+                            });
+                        },
+                    );
+                }
+            }
+        }
+
+        if !despawned {
+            commands.entity(entity).insert(Despawned);
+        }
+    }
+}
+
+fn update_boss_bar_distance_progress(
+    mut boss_bars_query: Query<(Ref<BossBarDistanceProgress>, &mut BossBarHealth)>,
+    tracked_query: Query<Ref<Position>>,
+) {
+    for (progress, mut health) in &mut boss_bars_query {
+        let Ok(position) = tracked_query.get(progress.tracked) else {
+            continue;
+        };
+
+        if !progress.is_changed() && !position.is_changed() {
+            continue;
+        }
+
+        let from = DVec3::new(
+            progress.from.x as f64,
+            progress.from.y as f64,
+            progress.from.z as f64,
+        );
+        let to = DVec3::new(
+            progress.to.x as f64,
+            progress.to.y as f64,
+            progress.to.z as f64,
         );
+
+        let path = to - from;
+        let length_squared = path.length_squared();
+
+        let fraction = if length_squared == 0.0 {
+            1.0
+        } else {
+            ((position.0 - from).dot(path) / length_squared).clamp(0.0, 1.0) as f32
+        };
+
+        if health.0 != fraction {
+            health.0 = fraction;
+        }
+    }
+}
+
+fn emit_boss_bar_thresholds_crossed(
+    boss_bars_query: Query<
+        (Entity, &BossBarHealth, Ref<BossBarThresholds>),
+        Or<(Changed<BossBarHealth>, Changed<BossBarThresholds>)>,
+    >,
+    mut removed_thresholds: RemovedComponents<BossBarThresholds>,
+    // For every threshold, whether the health is currently below it.
+    mut below: Local<HashMap<Entity, Vec<bool>>>,
+    mut events: EventWriter<BossBarHealthThresholdCrossed>,
+) {
+    for entity in removed_thresholds.read() {
+        below.remove(&entity);
+    }
+
+    for (entity, health, thresholds) in &boss_bars_query {
+        let below = match below.get_mut(&entity) {
+            Some(below) if !thresholds.is_changed() => below,
+            _ => {
+                // New or changed thresholds only record which side the health
+                // is on.
+                below.insert(entity, thresholds.0.iter().map(|&t| health.0 < t).collect());
+                continue;
+            }
+        };
+
+        let mut crossed_thresholds = vec![];
+
+        for (&threshold, below) in thresholds.0.iter().zip(below) {
+            let crossed = if *below {
+                health.0 > threshold + BOSS_BAR_THRESHOLD_DEBOUNCE
+            } else {
+                health.0 < threshold - BOSS_BAR_THRESHOLD_DEBOUNCE
+            };
+
+            if crossed {
+                *below = !*below;
+
+                crossed_thresholds.push(BossBarHealthThresholdCrossed {
+                    boss_bar: entity,
+                    threshold,
+                    rising: !*below,
+                });
+            }
+        }
+
+        // Send the events in the order the health passed the thresholds.
+        crossed_thresholds.sort_by(|a, b| {
+            if a.rising {
+                a.threshold.total_cmp(&b.threshold)
+            } else {
+                b.threshold.total_cmp(&a.threshold)
+            }
+        });
+
+        events.send_batch(crossed_thresholds);
+    }
+}
+
+/// Copies the parts of mirrored boss bars that changed this tick into their
+/// mirrors, or every part when the mirror is new. Mirrors keep their last state
+/// if the source is despawned.
+fn update_boss_bar_mirror(
+    mut mirrors_query: Query<(
+        Ref<BossBarMirror>,
+        &mut BossBarTitle,
+        &mut BossBarHealth,
+        &mut BossBarStyle,
+        &mut BossBarFlags,
+    )>,
+    sources_query: Query<
+        (
+            Ref<BossBarTitle>,
+            Ref<BossBarHealth>,
+            Ref<BossBarStyle>,
+            Ref<BossBarFlags>,
+        ),
+        Without<BossBarMirror>,
+    >,
+) {
+    for (mirror, mut title, mut health, mut style, mut flags) in &mut mirrors_query {
+        let Ok((src_title, src_health, src_style, src_flags)) = sources_query.get(mirror.0) else {
+            continue;
+        };
+
+        // A new or retargeted mirror copies everything from its source.
+        let all = mirror.is_changed();
+
+        if (all || src_title.is_changed()) && title.0 != src_title.0 {
+            title.0 = src_title.0.clone();
+        }
+
+        if (all || src_health.is_changed()) && health.0 != src_health.0 {
+            health.0 = src_health.0;
+        }
+
+        if (all || src_style.is_changed()) && *style != *src_style {
+            *style = *src_style;
+        }
+
+        if (all || src_flags.is_changed()) && *flags != *src_flags {
+            *flags = *src_flags;
+        }
+    }
+}
+
+fn update_boss_bar_overlay(
+    boss_bars_query: Query<
+        (&BossBarOverlay, &EntityLayerId, Option<&Position>),
+        (Changed<BossBarOverlay>, Without<BossBarHidden>),
+    >,
+    mut entity_layers_query: Query<&mut EntityLayer>,
+) {
+    for (overlay, entity_layer_id, pos) in boss_bars_query.iter() {
+        if let Ok(mut entity_layer) = entity_layers_query.get_mut(entity_layer_id.0) {
+            if let Some(pos) = pos {
+                entity_layer.view_writer(pos.0).set_action_bar(&overlay.0);
+            } else {
+                entity_layer.set_action_bar(&overlay.0);
+            }
+        }
+    }
+}
+
+/// Finds boss bars with more than one part changed since the last tick. These
+/// are sent in full by `update_boss_bar_coalesced` with a single `Add`, which
+/// replaces the boss bar in place, instead of one update per part.
+///
+/// Boss bars with [`BossBarPerViewer`] overrides are never coalesced, since
+/// their health differs between clients.
+#[derive(SystemParam)]
+struct CoalescedBossBars<'w, 's> {
+    boss_bars_query: Query<
+        'w,
+        's,
+        (
+            Ref<'static, BossBarTitle>,
+            Ref<'static, BossBarHealth>,
+            Ref<'static, BossBarStyle>,
+            Ref<'static, BossBarFlags>,
+            Option<&'static BossBarPerViewer>,
+        ),
+    >,
+}
+
+impl CoalescedBossBars<'_, '_> {
+    fn contains(&self, boss_bar: Entity) -> bool {
+        let Ok((title, health, style, flags, per_viewer)) = self.boss_bars_query.get(boss_bar)
+        else {
+            return false;
+        };
+
+        if per_viewer.is_some_and(|per_viewer| !per_viewer.is_empty()) {
+            return false;
+        }
+
+        let changed = [
+            title.is_changed(),
+            health.is_changed(),
+            style.is_changed(),
+            flags.is_changed(),
+        ];
+
+        changed.into_iter().filter(|&changed| changed).count() > 1
+    }
+}
+
+fn update_boss_bar_coalesced(
+    boss_bars_query: Query<
+        (
+            Entity,
+            Ref<UniqueId>,
+            &BossBarTitle,
+            &BossBarHealth,
+            &BossBarStyle,
+            &BossBarFlags,
+            &EntityLayerId,
+            Option<&Position>,
+        ),
+        (
+            Or<(
+                Changed<BossBarTitle>,
+                Changed<BossBarHealth>,
+                Changed<BossBarStyle>,
+                Changed<BossBarFlags>,
+            )>,
+            Without<BossBarHidden>,
+        ),
+    >,
+    coalesced: CoalescedBossBars,
+    mut entity_layers_query: Query<&mut EntityLayer>,
+) {
+    for (entity, id, title, health, style, flags, entity_layer_id, pos) in boss_bars_query.iter() {
+        // Newly spawned boss bars are sent in full by `boss_bar_spawn`.
+        if id.is_added() || !coalesced.contains(entity) {
+            continue;
+        }
+
+        if let Ok(mut entity_layer) = entity_layers_query.get_mut(entity_layer_id.0) {
+            let snapshot = BossBarSnapshot::new(title, health, style, flags);
+            let packet = BossBarS2c {
+                id: id.0,
+                action: snapshot.to_packet_action(),
+            };
+            if let Some(pos) = pos {
+                entity_layer.view_writer(pos.0).write_packet(&packet);
+            } else {
+                entity_layer.write_packet(&packet);
+            }
+        }
+    }
+}
+
+fn update_boss_bar_health(
+    boss_bars_query: Query<
+        (
+            Entity,
+            Ref<UniqueId>,
+            Ref<BossBarHealth>,
+            Option<Ref<BossBarPerViewer>>,
+            &EntityLayerId,
+            Option<&Position>,
+        ),
+        Without<BossBarHidden>,
+    >,
+    mut removed_per_viewer: RemovedComponents<BossBarPerViewer>,
+    coalesced: CoalescedBossBars,
+    mut entity_layers_query: Query<&mut EntityLayer>,
+    mut clients_query: Query<ViewerQueryData>,
+) {
+    let removed_per_viewer = removed_per_viewer.read().collect::<HashSet<_>>();
+
+    for (entity, id, health, per_viewer, entity_layer_id, pos) in boss_bars_query.iter() {
+        // Newly spawned boss bars are sent in full by `boss_bar_spawn`.
+        if id.is_added() || coalesced.contains(entity) {
+            continue;
+        }
+
+        let per_viewer_changed = match &per_viewer {
+            Some(per_viewer) => per_viewer.is_changed(),
+            None => removed_per_viewer.contains(&entity),
+        };
+
+        if !health.is_changed() && !per_viewer_changed {
+            continue;
+        }
+
+        match per_viewer.filter(|per_viewer| !per_viewer.is_empty()) {
+            Some(per_viewer) => {
+                for_each_viewer(
+                    &mut clients_query,
+                    entity_layer_id,
+                    pos,
+                    |client, writer| {
+                        writer.write_packet(&BossBarS2c {
+                            id: id.0,
+                            action: BossBarAction::UpdateHealth(
+                                per_viewer.health_for(client, &health),
+                            ),
+                        });
+                    },
+                );
+            }
+            None => {
+                if let Ok(mut entity_layer) = entity_layers_query.get_mut(entity_layer_id.0) {
+                    let packet = BossBarS2c {
+                        id: id.0,
+                        action: health.to_packet_action(),
+                    };
+                    if let Some(pos) = pos {
+                        entity_layer.view_writer(pos.0).write_packet(&packet);
+                    } else {
+                        entity_layer.write_packet(&packet);
+                    }
+                }
+            }
+        }
     }
 }
 
 fn update_boss_bar<T: Component + ToPacketAction>(
-    boss_bars_query: Query<(&UniqueId, &T, &EntityLayerId, Option<&Position>), Changed<T>>,
+    boss_bars_query: Query<
+        (Entity, Ref<UniqueId>, &T, &EntityLayerId, Option<&Position>),
+        (Changed<T>, Without<BossBarHidden>),
+    >,
+    coalesced: CoalescedBossBars,
     mut entity_layers_query: Query<&mut EntityLayer>,
 ) {
-    for (id, part, entity_layer_id, pos) in boss_bars_query.iter() {
+    for (entity, id, part, entity_layer_id, pos) in boss_bars_query.iter() {
+        // Newly spawned boss bars are sent in full by `boss_bar_spawn`.
+        if id.is_added() || coalesced.contains(entity) {
+            continue;
+        }
+
         if let Ok(mut entity_layer) = entity_layers_query.get_mut(entity_layer_id.0) {
             let packet = BossBarS2c {
                 id: id.0,
@@ -76,9 +1021,225 @@ fn update_boss_bar<T: Component + ToPacketAction>(
     }
 }
 
+/// Sends boss bars with [`BossBarResync`] in full and removes the marker.
+fn update_boss_bar_resync(
+    boss_bars_query: Query<
+        (
+            Entity,
+            Ref<UniqueId>,
+            &BossBarTitle,
+            &BossBarHealth,
+            &BossBarStyle,
+            &BossBarFlags,
+            &EntityLayerId,
+            Option<&Position>,
+            Option<&BossBarPerViewer>,
+            Has<BossBarHidden>,
+        ),
+        With<BossBarResync>,
+    >,
+    mut entity_layers_query: Query<&mut EntityLayer>,
+    mut clients_query: Query<ViewerQueryData>,
+    mut commands: Commands,
+) {
+    for (entity, id, title, health, style, flags, entity_layer_id, pos, per_viewer, hidden) in
+        boss_bars_query.iter()
+    {
+        commands.entity(entity).remove::<BossBarResync>();
+
+        // Newly spawned boss bars are sent in full by `boss_bar_spawn`.
+        if hidden || id.is_added() {
+            continue;
+        }
+
+        let snapshot = BossBarSnapshot::new(title, health, style, flags);
+        let add_packet = |health| BossBarS2c {
+            id: id.0,
+            action: snapshot.with_health(health).add_action(),
+        };
+
+        if let Some(per_viewer) = per_viewer.filter(|p| !p.is_empty()) {
+            for_each_viewer(
+                &mut clients_query,
+                entity_layer_id,
+                pos,
+                |client, writer| {
+                    writer.write_packet(&add_packet(per_viewer.health_for(client, health)));
+                },
+            );
+        } else if let Ok(mut entity_layer) = entity_layers_query.get_mut(entity_layer_id.0) {
+            let packet = add_packet(health.get());
+            if let Some(pos) = pos {
+                entity_layer.view_writer(pos.0).write_packet(&packet);
+            } else {
+                entity_layer.write_packet(&packet);
+            }
+        }
+    }
+}
+
+/// Like [`update_boss_bar`], but only sends the title when it differs from the
+/// last title that was sent. Titles are often rebuilt every tick, which would
+/// otherwise trigger change detection without changing anything.
+///
+/// `last_sent` holds the current title of every boss bar whose title has
+/// changed, including hidden ones. Every other path that sends a boss bar in
+/// full, such as revealing it or adding a viewer, sends the current title, so
+/// this is always the title that clients have.
+fn update_boss_bar_title(
+    boss_bars_query: Query<
+        (
+            Entity,
+            Ref<UniqueId>,
+            &BossBarTitle,
+            &EntityLayerId,
+            Option<&Position>,
+            Has<BossBarHidden>,
+        ),
+        Changed<BossBarTitle>,
+    >,
+    coalesced: CoalescedBossBars,
+    mut entity_layers_query: Query<&mut EntityLayer>,
+    mut removed_titles: RemovedComponents<BossBarTitle>,
+    mut last_sent: Local<HashMap<Entity, Text>>,
+) {
+    for entity in removed_titles.read() {
+        last_sent.remove(&entity);
+    }
+
+    for (entity, id, title, entity_layer_id, pos, hidden) in boss_bars_query.iter() {
+        if last_sent.get(&entity) == Some(&title.0) {
+            continue;
+        }
+
+        last_sent.insert(entity, title.0.clone());
+
+        // Newly spawned boss bars are sent in full by `boss_bar_spawn`, and
+        // hidden boss bars are sent in full when they are revealed.
+        if id.is_added() || hidden || coalesced.contains(entity) {
+            continue;
+        }
+
+        if let Ok(mut entity_layer) = entity_layers_query.get_mut(entity_layer_id.0) {
+            let packet = BossBarS2c {
+                id: id.0,
+                action: title.to_packet_action(),
+            };
+            if let Some(pos) = pos {
+                entity_layer.view_writer(pos.0).write_packet(&packet);
+            } else {
+                entity_layer.write_packet(&packet);
+            }
+        }
+    }
+}
+
+/// Sends `Remove` when a visible boss bar is hidden and `Add` when a hidden
+/// boss bar is revealed. Boss bars spawned hidden send nothing.
+fn update_boss_bar_hidden(
+    hidden_query: Query<
+        (Entity, Ref<UniqueId>, &EntityLayerId, Option<&Position>),
+        Added<BossBarHidden>,
+    >,
+    mut revealed: RemovedComponents<BossBarHidden>,
+    boss_bars_query: Query<
+        (
+            &UniqueId,
+            &BossBarTitle,
+            &BossBarHealth,
+            &BossBarStyle,
+            &BossBarFlags,
+            &EntityLayerId,
+            Option<&Position>,
+            Option<&BossBarPerViewer>,
+            Option<Ref<BossBarOverlay>>,
+        ),
+        (Without<BossBarHidden>, Without<Despawned>),
+    >,
+    mut entity_layers_query: Query<&mut EntityLayer>,
+    mut clients_query: Query<ViewerQueryData>,
+    mut viewer_added_events: EventWriter<BossBarViewerAdded>,
+    mut viewer_removed_events: EventWriter<BossBarViewerRemoved>,
+) {
+    for (boss_bar, id, entity_layer_id, pos) in hidden_query.iter() {
+        // Clients never saw a boss bar that was spawned hidden.
+        if id.is_added() {
+            continue;
+        }
+
+        if let Ok(mut entity_layer) = entity_layers_query.get_mut(entity_layer_id.0) {
+            let packet = BossBarS2c {
+                id: id.0,
+                action: BossBarAction::Remove,
+            };
+            if let Some(pos) = pos {
+                entity_layer.view_writer(pos.0).write_packet(&packet);
+            } else {
+                entity_layer.write_packet(&packet);
+            }
+
+            for_each_viewer_entity(&clients_query, entity_layer_id, pos, |client| {
+                viewer_removed_events.send(BossBarViewerRemoved { boss_bar, client });
+            });
+        }
+    }
+
+    for boss_bar in revealed.read() {
+        let Ok((id, title, health, style, flags, entity_layer_id, pos, per_viewer, overlay)) =
+            boss_bars_query.get(boss_bar)
+        else {
+            continue;
+        };
+
+        for_each_viewer_entity(&clients_query, entity_layer_id, pos, |client| {
+            viewer_added_events.send(BossBarViewerAdded { boss_bar, client });
+        });
+
+        let snapshot = BossBarSnapshot::new(title, health, style, flags);
+        let add_packet = |health| BossBarS2c {
+            id: id.0,
+            action: snapshot.with_health(health).add_action(),
+        };
+
+        if let Some(per_viewer) = per_viewer.filter(|p| !p.is_empty()) {
+            for_each_viewer(
+                &mut clients_query,
+                entity_layer_id,
+                pos,
+                |client, writer| {
+                    writer.write_packet(&add_packet(per_viewer.health_for(client, health)));
+                },
+            );
+        } else if let Ok(mut entity_layer) = entity_layers_query.get_mut(entity_layer_id.0) {
+            let packet = add_packet(health.get());
+            if let Some(pos) = pos {
+                entity_layer.view_writer(pos.0).write_packet(&packet);
+            } else {
+                entity_layer.write_packet(&packet);
+            }
+        }
+
+        // A changed overlay is already sent by `update_boss_bar_overlay`.
+        let Some(overlay) = overlay else {
+            continue;
+        };
+
+        if !overlay.is_changed() {
+            if let Ok(mut entity_layer) = entity_layers_query.get_mut(entity_layer_id.0) {
+                if let Some(pos) = pos {
+                    entity_layer.view_writer(pos.0).set_action_bar(&overlay.0);
+                } else {
+                    entity_layer.set_action_bar(&overlay.0);
+                }
+            }
+        }
+    }
+}
+
 fn update_boss_bar_layer_view(
     mut clients_query: Query<
         (
+            Entity,
             &mut Client,
             &VisibleEntityLayers,
             &OldVisibleEntityLayers,
@@ -89,17 +1250,26 @@ fn update_boss_bar_layer_view(
         ),
         Changed<VisibleEntityLayers>,
     >,
-    boss_bars_query: Query<(
-        &UniqueId,
-        &BossBarTitle,
-        &BossBarHealth,
-        &BossBarStyle,
-        &BossBarFlags,
-        &EntityLayerId,
-        Option<&Position>,
-    )>,
+    boss_bars_query: Query<
+        (
+            Entity,
+            &UniqueId,
+            &BossBarTitle,
+            &BossBarHealth,
+            &BossBarStyle,
+            &BossBarFlags,
+            &EntityLayerId,
+            Option<&Position>,
+            Option<&BossBarPerViewer>,
+        ),
+        Without<BossBarHidden>,
+    >,
+    overlays_query: Query<&BossBarOverlay>,
+    mut viewer_added_events: EventWriter<BossBarViewerAdded>,
+    mut viewer_removed_events: EventWriter<BossBarViewerRemoved>,
 ) {
     for (
+        client_entity,
         mut client,
         visible_entity_layers,
         old_visible_entity_layers,
@@ -110,60 +1280,56 @@ fn update_boss_bar_layer_view(
     ) in clients_query.iter_mut()
     {
         let view = ChunkView::new(position.0.into(), view_distance.get());
+        let in_view = |boss_bar_position: Option<&Position>| match boss_bar_position {
+            Some(position) => view.contains(position.0.into()),
+            None => true,
+        };
 
         let old_layers = old_visible_entity_layers.get();
         let current_layers = &visible_entity_layers.0;
 
         for &added_layer in current_layers.difference(old_layers) {
-            for (id, title, health, style, flags, _, boss_bar_position) in boss_bars_query
-                .iter()
-                .filter(|(_, _, _, _, _, layer_id, _)| layer_id.0 == added_layer)
+            for (boss_bar, id, title, health, style, flags, _, boss_bar_position, per_viewer) in
+                boss_bars_query
+                    .iter()
+                    .filter(|(_, _, _, _, _, _, layer_id, _, _)| layer_id.0 == added_layer)
             {
-                if let Some(position) = boss_bar_position {
-                    if view.contains(position.0.into()) {
-                        client.write_packet(&BossBarS2c {
-                            id: id.0,
-                            action: BossBarAction::Add {
-                                title: Cow::Borrowed(&title.0),
-                                health: health.0,
-                                color: style.color,
-                                division: style.division,
-                                flags: *flags,
-                            },
-                        });
+                if in_view(boss_bar_position) {
+                    let mut snapshot = BossBarSnapshot::new(title, health, style, flags);
+                    if let Some(per_viewer) = per_viewer {
+                        snapshot =
+                            snapshot.with_health(per_viewer.health_for(client_entity, health));
                     }
-                } else {
+
                     client.write_packet(&BossBarS2c {
                         id: id.0,
-                        action: BossBarAction::Add {
-                            title: Cow::Borrowed(&title.0),
-                            health: health.0,
-                            color: style.color,
-                            division: style.division,
-                            flags: *flags,
-                        },
+                        action: snapshot.add_action(),
+                    });
+                    if let Ok(overlay) = overlays_query.get(boss_bar) {
+                        client.set_action_bar(&overlay.0);
+                    }
+                    viewer_added_events.send(BossBarViewerAdded {
+                        boss_bar,
+                        client: client_entity,
                     });
                 }
             }
         }
 
         for &removed_layer in old_layers.difference(current_layers) {
-            for (id, _, _, _, _, _, boss_bar_position) in boss_bars_query
+            for (boss_bar, id, _, _, _, _, _, boss_bar_position, _) in boss_bars_query
                 .iter()
-                .filter(|(_, _, _, _, _, layer_id, _)| layer_id.0 == removed_layer)
+                .filter(|(_, _, _, _, _, _, layer_id, _, _)| layer_id.0 == removed_layer)
             {
-                if let Some(position) = boss_bar_position {
-                    if view.contains(position.0.into()) {
-                        client.write_packet(&BossBarS2c {
-                            id: id.0,
-                            action: BossBarAction::Remove,
-                        });
-                    }
-                } else {
+                if in_view(boss_bar_position) {
                     client.write_packet(&BossBarS2c {
                         id: id.0,
                         action: BossBarAction::Remove,
                     });
+                    viewer_removed_events.send(BossBarViewerRemoved {
+                        boss_bar,
+                        client: client_entity,
+                    });
                 }
             }
         }
@@ -171,77 +1337,205 @@ fn update_boss_bar_layer_view(
 }
 
 fn update_boss_bar_chunk_view(
-    mut clients_query: Query<
+    mut clients_query: Query<(
+        Entity,
+        &mut Client,
+        &VisibleEntityLayers,
+        &OldVisibleEntityLayers,
+        Ref<Position>,
+        &OldPosition,
+        Ref<ViewDistance>,
+        &OldViewDistance,
+    )>,
+    boss_bars_query: Query<
         (
-            &mut Client,
-            &VisibleEntityLayers,
-            &OldVisibleEntityLayers,
+            Entity,
+            &UniqueId,
+            &BossBarTitle,
+            &BossBarHealth,
+            &BossBarStyle,
+            &BossBarFlags,
+            &EntityLayerId,
             &Position,
-            &OldPosition,
-            &ViewDistance,
-            &OldViewDistance,
+            Option<&BossBarViewerGrace>,
+            Option<&BossBarPerViewer>,
         ),
-        Changed<Position>,
+        Without<BossBarHidden>,
     >,
-    boss_bars_query: Query<(
-        &UniqueId,
-        &BossBarTitle,
-        &BossBarHealth,
-        &BossBarStyle,
-        &BossBarFlags,
-        &EntityLayerId,
-        &Position,
-    )>,
+    overlays_query: Query<&BossBarOverlay>,
+    mut viewer_added_events: EventWriter<BossBarViewerAdded>,
+    mut viewer_removed_events: EventWriter<BossBarViewerRemoved>,
+    // The chunk each boss bar was in last tick.
+    mut last_chunks: Local<HashMap<Entity, ChunkPos>>,
+    // Removals deferred by `BossBarViewerGrace`, keyed by client and boss bar.
+    mut pending_removals: Local<HashMap<(Entity, Entity), (Uuid, u32)>>,
 ) {
+    // Boss bars that moved to another chunk this tick, with their layer and
+    // the chunks they moved from and to. Boss bars seen for the first time were
+    // just spawned or revealed, which is handled elsewhere.
+    let mut moved = HashMap::new();
+
+    for (boss_bar_entity, .., layer_id, boss_bar_position, _, _) in boss_bars_query.iter() {
+        let chunk = ChunkPos::from(boss_bar_position.0);
+
+        if let Some(old_chunk) = last_chunks.insert(boss_bar_entity, chunk) {
+            if old_chunk != chunk {
+                moved.insert(boss_bar_entity, (layer_id.0, old_chunk, chunk));
+            }
+        }
+    }
+
+    last_chunks.retain(|&boss_bar_entity, _| boss_bars_query.contains(boss_bar_entity));
+
+    // Clients that started (`true`) or stopped (`false`) seeing a boss bar,
+    // keyed by client and boss bar.
+    let mut transitions = vec![];
+
     for (
-        mut client,
+        client_entity,
+        _,
         visible_entity_layers,
-        _old_visible_entity_layers,
+        old_visible_entity_layers,
         position,
         old_position,
         view_distance,
         old_view_distance,
-    ) in clients_query.iter_mut()
+    ) in clients_query.iter()
     {
+        let client_moved = position.is_changed() || view_distance.is_changed();
+        if !client_moved && moved.is_empty() {
+            continue;
+        }
+
         let view = ChunkView::new(position.0.into(), view_distance.get());
         let old_view = ChunkView::new(old_position.get().into(), old_view_distance.get());
 
-        for layer in visible_entity_layers.0.iter() {
-            for (id, title, health, style, flags, _, boss_bar_position) in boss_bars_query
-                .iter()
-                .filter(|(_, _, _, _, _, layer_id, _)| layer_id.0 == *layer)
+        // Boss bars on layers that were added or removed this tick are handled
+        // by `update_boss_bar_layer_view`.
+        let on_kept_layer = |layer: &Entity| {
+            visible_entity_layers.0.contains(layer) && old_visible_entity_layers.contains(layer)
+        };
+
+        let mut check = |boss_bar_entity, old_chunk, chunk| {
+            let was_visible = old_view.contains(old_chunk);
+            let is_visible = view.contains(chunk);
+
+            if was_visible != is_visible {
+                transitions.push(((client_entity, boss_bar_entity), is_visible));
+            }
+        };
+
+        if client_moved {
+            for (boss_bar_entity, .., layer_id, boss_bar_position, _, _) in boss_bars_query.iter() {
+                if !on_kept_layer(&layer_id.0) {
+                    continue;
+                }
+
+                let chunk = ChunkPos::from(boss_bar_position.0);
+                let old_chunk = moved
+                    .get(&boss_bar_entity)
+                    .map_or(chunk, |&(_, old_chunk, _)| old_chunk);
+
+                check(boss_bar_entity, old_chunk, chunk);
+            }
+        } else {
+            // Only the boss bars that moved can have changed visibility.
+            for (&boss_bar_entity, &(layer, old_chunk, chunk)) in &moved {
+                if on_kept_layer(&layer) {
+                    check(boss_bar_entity, old_chunk, chunk);
+                }
+            }
+        }
+    }
+
+    for ((client_entity, boss_bar_entity), is_visible) in transitions {
+        let Ok((_, mut client, ..)) = clients_query.get_mut(client_entity) else {
+            continue;
+        };
+        let Ok((_, id, title, health, style, flags, _, _, grace, per_viewer)) =
+            boss_bars_query.get(boss_bar_entity)
+        else {
+            continue;
+        };
+
+        if is_visible {
+            // The boss bar is still on screen if its removal was deferred.
+            // Sending it in full replaces it in place and catches up on
+            // updates missed while out of view.
+            if pending_removals
+                .remove(&(client_entity, boss_bar_entity))
+                .is_none()
             {
-                if view.contains(boss_bar_position.0.into())
-                    && !old_view.contains(boss_bar_position.0.into())
-                {
-                    client.write_packet(&BossBarS2c {
-                        id: id.0,
-                        action: BossBarAction::Add {
-                            title: Cow::Borrowed(&title.0),
-                            health: health.0,
-                            color: style.color,
-                            division: style.division,
-                            flags: *flags,
-                        },
-                    });
-                } else if !view.contains(boss_bar_position.0.into())
-                    && old_view.contains(boss_bar_position.0.into())
-                {
+                viewer_added_events.send(BossBarViewerAdded {
+                    boss_bar: boss_bar_entity,
+                    client: client_entity,
+                });
+            }
+
+            let mut snapshot = BossBarSnapshot::new(title, health, style, flags);
+            if let Some(per_viewer) = per_viewer {
+                snapshot = snapshot.with_health(per_viewer.health_for(client_entity, health));
+            }
+
+            client.write_packet(&BossBarS2c {
+                id: id.0,
+                action: snapshot.add_action(),
+            });
+
+            if let Ok(overlay) = overlays_query.get(boss_bar_entity) {
+                client.set_action_bar(&overlay.0);
+            }
+        } else {
+            match grace {
+                Some(grace) if grace.ticks > 0 => {
+                    pending_removals.insert((client_entity, boss_bar_entity), (id.0, grace.ticks));
+                }
+                _ => {
                     client.write_packet(&BossBarS2c {
                         id: id.0,
                         action: BossBarAction::Remove,
                     });
+                    viewer_removed_events.send(BossBarViewerRemoved {
+                        boss_bar: boss_bar_entity,
+                        client: client_entity,
+                    });
                 }
             }
         }
     }
+
+    pending_removals.retain(|&(client_entity, boss_bar), (id, ticks)| {
+        if *ticks > 0 {
+            *ticks -= 1;
+            return true;
+        }
+
+        if let Ok((_, mut client, ..)) = clients_query.get_mut(client_entity) {
+            client.write_packet(&BossBarS2c {
+                id: *id,
+                action: BossBarAction::Remove,
+            });
+            viewer_removed_events.send(BossBarViewerRemoved {
+                boss_bar,
+                client: client_entity,
+            });
+        }
+
+        false
+    });
 }
 
 fn boss_bar_despawn(
-    boss_bars_query: Query<(&UniqueId, &EntityLayerId, Option<&Position>), With<Despawned>>,
-    mut entity_layer_query: Query<&mut EntityLayer>,
+    boss_bars_query: Query<
+        (Entity, &UniqueId, &EntityLayerId, Option<&Position>),
+        (With<Despawned>, Without<BossBarHidden>),
+    >,
+    // Boss bars on despawned layers are removed by `despawn_orphaned_boss_bars`.
+    mut entity_layer_query: Query<&mut EntityLayer, Without<Despawned>>,
+    clients_query: Query<ViewerQueryData>,
+    mut viewer_removed_events: EventWriter<BossBarViewerRemoved>,
 ) {
-    for (id, entity_layer_id, position) in boss_bars_query.iter() {
+    for (boss_bar, id, entity_layer_id, position) in boss_bars_query.iter() {
         if let Ok(mut entity_layer) = entity_layer_query.get_mut(entity_layer_id.0) {
             let packet = BossBarS2c {
                 id: id.0,
@@ -252,6 +1546,10 @@ fn boss_bar_despawn(
             } else {
                 entity_layer.write_packet(&packet);
             }
+
+            for_each_viewer_entity(&clients_query, entity_layer_id, position, |client| {
+                viewer_removed_events.send(BossBarViewerRemoved { boss_bar, client });
+            });
         }
     }
 }
@@ -470,4 +470,62 @@ mod tests {
         check_test_packet(&mut dec, "fourth");
         check_test_packet(&mut dec, "third");
     }
+
+    #[derive(Encode, Decode, Debug)]
+    struct BoundedFields<'a> {
+        list: Bounded<Vec<i32>, 4>,
+        name: Bounded<&'a str, 8>,
+    }
+
+    #[test]
+    fn bounded_rejects_oversized_length_prefix() {
+        let mut buf = vec![];
+
+        BoundedFields {
+            list: Bounded(vec![1, 2, 3, 4]),
+            name: Bounded("12345678"),
+        }
+        .encode(&mut buf)
+        .unwrap();
+
+        let fields = BoundedFields::decode(&mut buf.as_slice()).unwrap();
+        assert_eq!(fields.list.0, [1, 2, 3, 4]);
+        assert_eq!(fields.name.0, "12345678");
+
+        // A crafted length prefix far beyond the bound is rejected before
+        // anything is allocated for it.
+        buf.clear();
+        VarInt(i32::MAX).encode(&mut buf).unwrap();
+
+        let err = BoundedFields::decode(&mut buf.as_slice()).unwrap_err();
+        assert!(format!("{err:#}").contains("exceeds max of 4"), "{err:#}");
+
+        buf.clear();
+        VarInt(0).encode(&mut buf).unwrap();
+        VarInt(i32::MAX).encode(&mut buf).unwrap();
+
+        assert!(BoundedFields::decode(&mut buf.as_slice()).is_err());
+
+        // Unbounded sequences fail when the input runs out instead of
+        // preallocating the claimed length.
+        buf.clear();
+        VarInt(i32::MAX).encode(&mut buf).unwrap();
+
+        assert!(Vec::<u64>::decode(&mut buf.as_slice()).is_err());
+    }
+
+    #[test]
+    fn command_execution_rejects_too_many_argument_signatures() {
+        use crate::packets::play::CommandExecutionC2s;
+
+        let mut buf = vec![];
+
+        "tp".encode(&mut buf).unwrap();
+        0_u64.encode(&mut buf).unwrap();
+        0_u64.encode(&mut buf).unwrap();
+        VarInt(9).encode(&mut buf).unwrap();
+
+        let err = CommandExecutionC2s::decode(&mut buf.as_slice()).unwrap_err();
+        assert!(format!("{err:#}").contains("exceeds max of 8"), "{err:#}");
+    }
 }
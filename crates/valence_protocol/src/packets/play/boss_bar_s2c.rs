@@ -1,7 +1,10 @@
 use std::borrow::Cow;
+use std::fmt;
+use std::str::FromStr;
 
 use bevy_ecs::prelude::Component;
 use bitfield_struct::bitfield;
+use thiserror::Error;
 use uuid::Uuid;
 use valence_text::Text;
 
@@ -30,7 +33,7 @@ pub enum BossBarAction<'a> {
 }
 
 /// The color of a boss bar.
-#[derive(Copy, Clone, PartialEq, Eq, Debug, Encode, Decode, Default)]
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Encode, Decode, Default)]
 pub enum BossBarColor {
     #[default]
     Pink,
@@ -43,7 +46,7 @@ pub enum BossBarColor {
 }
 
 /// The division of a boss bar.
-#[derive(Copy, Clone, PartialEq, Eq, Debug, Encode, Decode, Default)]
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Encode, Decode, Default)]
 pub enum BossBarDivision {
     #[default]
     NoDivision,
@@ -53,6 +56,124 @@ pub enum BossBarDivision {
     TwentyNotches,
 }
 
+impl BossBarColor {
+    /// Returns every color, in protocol order.
+    pub const fn all() -> &'static [Self] {
+        &[
+            Self::Pink,
+            Self::Blue,
+            Self::Red,
+            Self::Green,
+            Self::Yellow,
+            Self::Purple,
+            Self::White,
+        ]
+    }
+
+    const fn name(self) -> &'static str {
+        match self {
+            Self::Pink => "pink",
+            Self::Blue => "blue",
+            Self::Red => "red",
+            Self::Green => "green",
+            Self::Yellow => "yellow",
+            Self::Purple => "purple",
+            Self::White => "white",
+        }
+    }
+}
+
+impl fmt::Display for BossBarColor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for BossBarColor {
+    type Err = ParseBossBarColorError;
+
+    /// Parses a color from its name, such as `"pink"`, ignoring case.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::all()
+            .iter()
+            .copied()
+            .find(|color| color.name().eq_ignore_ascii_case(s))
+            .ok_or_else(|| ParseBossBarColorError(s.to_owned()))
+    }
+}
+
+/// The error returned when parsing a [`BossBarColor`] fails.
+#[derive(Clone, PartialEq, Eq, Debug, Error)]
+#[error("unknown boss bar color `{0}`")]
+pub struct ParseBossBarColorError(pub String);
+
+impl BossBarDivision {
+    /// Returns every division, in protocol order.
+    pub const fn all() -> &'static [Self] {
+        &[
+            Self::NoDivision,
+            Self::SixNotches,
+            Self::TenNotches,
+            Self::TwelveNotches,
+            Self::TwentyNotches,
+        ]
+    }
+
+    const fn name(self) -> &'static str {
+        match self {
+            Self::NoDivision => "no_division",
+            Self::SixNotches => "six_notches",
+            Self::TenNotches => "ten_notches",
+            Self::TwelveNotches => "twelve_notches",
+            Self::TwentyNotches => "twenty_notches",
+        }
+    }
+
+    /// Other accepted names: the number of notches and the name used by the
+    /// vanilla `/bossbar` command.
+    const fn aliases(self) -> [&'static str; 2] {
+        match self {
+            Self::NoDivision => ["none", "progress"],
+            Self::SixNotches => ["six", "notched_6"],
+            Self::TenNotches => ["ten", "notched_10"],
+            Self::TwelveNotches => ["twelve", "notched_12"],
+            Self::TwentyNotches => ["twenty", "notched_20"],
+        }
+    }
+}
+
+impl fmt::Display for BossBarDivision {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for BossBarDivision {
+    type Err = ParseBossBarDivisionError;
+
+    /// Parses a division from its name, such as `"ten_notches"`, ignoring
+    /// case. The number of notches, such as `"ten"`, and the names used by the
+    /// vanilla `/bossbar` command, such as `"notched_10"`, are also accepted.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::all()
+            .iter()
+            .copied()
+            .find(|division| {
+                division.name().eq_ignore_ascii_case(s)
+                    || division
+                        .aliases()
+                        .iter()
+                        .any(|alias| alias.eq_ignore_ascii_case(s))
+            })
+            .ok_or_else(|| ParseBossBarDivisionError(s.to_owned()))
+    }
+}
+
+/// The error returned when parsing a [`BossBarDivision`] fails.
+#[derive(Clone, PartialEq, Eq, Debug, Error)]
+#[error("unknown boss bar division `{0}`")]
+pub struct ParseBossBarDivisionError(pub String);
+
 /// The flags of a boss bar (darken sky, dragon bar, create fog).
 #[bitfield(u8)]
 #[derive(PartialEq, Eq, Encode, Decode, Component)]
@@ -63,3 +184,231 @@ pub struct BossBarFlags {
     #[bits(5)]
     _pad: u8,
 }
+
+impl BossBarFlags {
+    /// Creates flags from an iterator of flag names. The names are
+    /// `"darken_sky"`, `"dragon_bar"` and `"create_fog"`. Unknown names are
+    /// ignored.
+    pub fn from_names<'a>(names: impl IntoIterator<Item = &'a str>) -> Self {
+        let mut flags = Self::new();
+
+        for name in names {
+            match name {
+                "darken_sky" => flags.set_darken_sky(true),
+                "dragon_bar" => flags.set_dragon_bar(true),
+                "create_fog" => flags.set_create_fog(true),
+                _ => {}
+            }
+        }
+
+        flags
+    }
+
+    /// Returns flags with none of the flags set.
+    pub const fn none() -> Self {
+        Self::new()
+    }
+
+    /// Returns the flags of the ender dragon's boss bar, which has every flag
+    /// set.
+    pub const fn dragon() -> Self {
+        Self::new()
+            .with_darken_sky(true)
+            .with_dragon_bar(true)
+            .with_create_fog(true)
+    }
+
+    /// Returns whether `flag` is set.
+    pub const fn get(self, flag: BossBarFlag) -> bool {
+        match flag {
+            BossBarFlag::DarkenSky => self.darken_sky(),
+            BossBarFlag::DragonBar => self.dragon_bar(),
+            BossBarFlag::CreateFog => self.create_fog(),
+        }
+    }
+
+    /// Returns these flags with `flag` set to `value`.
+    pub const fn with(self, flag: BossBarFlag, value: bool) -> Self {
+        match flag {
+            BossBarFlag::DarkenSky => self.with_darken_sky(value),
+            BossBarFlag::DragonBar => self.with_dragon_bar(value),
+            BossBarFlag::CreateFog => self.with_create_fog(value),
+        }
+    }
+
+    /// Returns these flags with `flag` flipped.
+    pub const fn toggled(self, flag: BossBarFlag) -> Self {
+        self.with(flag, !self.get(flag))
+    }
+}
+
+/// A single flag of [`BossBarFlags`].
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub enum BossBarFlag {
+    DarkenSky,
+    DragonBar,
+    CreateFog,
+}
+
+impl BossBarFlag {
+    /// Returns every flag, in bit order.
+    pub const fn all() -> &'static [Self] {
+        &[Self::DarkenSky, Self::DragonBar, Self::CreateFog]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::VarInt;
+
+    #[test]
+    fn flags_from_names() {
+        let flags = BossBarFlags::from_names(["darken_sky", "create_fog", "unknown"]);
+
+        assert!(flags.darken_sky());
+        assert!(!flags.dragon_bar());
+        assert!(flags.create_fog());
+        assert_eq!(
+            flags,
+            BossBarFlags::new()
+                .with_darken_sky(true)
+                .with_create_fog(true)
+        );
+    }
+
+    #[test]
+    fn flag_presets() {
+        assert_eq!(BossBarFlags::none(), BossBarFlags::new());
+        assert_eq!(u8::from(BossBarFlags::none()), 0b000);
+
+        assert_eq!(
+            BossBarFlags::dragon(),
+            BossBarFlags::new()
+                .with_darken_sky(true)
+                .with_dragon_bar(true)
+                .with_create_fog(true)
+        );
+        assert_eq!(u8::from(BossBarFlags::dragon()), 0b111);
+
+        let mut flags = BossBarFlags::none();
+        for (bit, &flag) in BossBarFlag::all().iter().enumerate() {
+            assert!(!flags.get(flag));
+            flags = flags.toggled(flag);
+            assert!(flags.get(flag));
+            assert_eq!(u8::from(flags), (1 << (bit + 1)) - 1);
+        }
+
+        assert_eq!(flags, BossBarFlags::dragon());
+        assert_eq!(
+            flags.toggled(BossBarFlag::DragonBar),
+            BossBarFlags::new()
+                .with_darken_sky(true)
+                .with_create_fog(true)
+        );
+    }
+
+    #[test]
+    fn style_enums_are_ordered() {
+        use std::collections::BTreeSet;
+
+        let colors: BTreeSet<_> = [
+            BossBarColor::White,
+            BossBarColor::Purple,
+            BossBarColor::Yellow,
+            BossBarColor::Green,
+            BossBarColor::Red,
+            BossBarColor::Blue,
+            BossBarColor::Pink,
+        ]
+        .into_iter()
+        .collect();
+
+        assert!(colors.into_iter().eq([
+            BossBarColor::Pink,
+            BossBarColor::Blue,
+            BossBarColor::Red,
+            BossBarColor::Green,
+            BossBarColor::Yellow,
+            BossBarColor::Purple,
+            BossBarColor::White,
+        ]));
+
+        let divisions: BTreeSet<_> = [
+            BossBarDivision::TwentyNotches,
+            BossBarDivision::NoDivision,
+            BossBarDivision::TenNotches,
+            BossBarDivision::SixNotches,
+            BossBarDivision::TwelveNotches,
+        ]
+        .into_iter()
+        .collect();
+
+        assert!(divisions.into_iter().eq([
+            BossBarDivision::NoDivision,
+            BossBarDivision::SixNotches,
+            BossBarDivision::TenNotches,
+            BossBarDivision::TwelveNotches,
+            BossBarDivision::TwentyNotches,
+        ]));
+    }
+
+    #[test]
+    fn style_enums_round_trip_through_strings() {
+        for &color in BossBarColor::all() {
+            assert_eq!(color.to_string().parse(), Ok(color));
+            assert_eq!(color.to_string().to_uppercase().parse(), Ok(color));
+        }
+
+        for &division in BossBarDivision::all() {
+            assert_eq!(division.to_string().parse(), Ok(division));
+            assert_eq!(division.to_string().to_uppercase().parse(), Ok(division));
+        }
+
+        assert_eq!("Pink".parse(), Ok(BossBarColor::Pink));
+        assert_eq!("twelve".parse(), Ok(BossBarDivision::TwelveNotches));
+        assert_eq!("notched_20".parse(), Ok(BossBarDivision::TwentyNotches));
+        assert_eq!("progress".parse(), Ok(BossBarDivision::NoDivision));
+
+        assert_eq!(
+            "orange".parse::<BossBarColor>(),
+            Err(ParseBossBarColorError("orange".into()))
+        );
+        assert_eq!(
+            "seven".parse::<BossBarDivision>().unwrap_err().to_string(),
+            "unknown boss bar division `seven`"
+        );
+    }
+
+    fn decode_discriminant<T: for<'a> Decode<'a>>(
+        buf: &mut Vec<u8>,
+        disc: i32,
+    ) -> anyhow::Result<T> {
+        buf.clear();
+        VarInt(disc).encode(&mut *buf).unwrap();
+        T::decode(&mut buf.as_slice())
+    }
+
+    #[test]
+    fn decode_style_discriminants() {
+        let mut buf = vec![];
+
+        for disc in 0..7 {
+            decode_discriminant::<BossBarColor>(&mut buf, disc).unwrap();
+        }
+
+        for disc in 0..5 {
+            decode_discriminant::<BossBarDivision>(&mut buf, disc).unwrap();
+        }
+
+        for disc in [-1, 7, 100, i32::MAX] {
+            let err = decode_discriminant::<BossBarColor>(&mut buf, disc).unwrap_err();
+            assert!(err.to_string().contains("unexpected enum discriminant"));
+        }
+
+        for disc in [-1, 5, 100, i32::MIN] {
+            let err = decode_discriminant::<BossBarDivision>(&mut buf, disc).unwrap_err();
+            assert!(err.to_string().contains("unexpected enum discriminant"));
+        }
+    }
+}
@@ -7,6 +7,7 @@
 pub mod bvh;
 pub mod chunk;
 pub mod entity;
+pub mod entity_bvh;
 pub mod message;
 
 use bevy_app::prelude::*;
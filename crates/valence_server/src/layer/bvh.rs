@@ -1,15 +1,29 @@
-use std::mem;
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::f64::consts::FRAC_PI_2;
 use std::ops::Range;
+use std::{fmt, mem};
 
+use valence_generated::chunk_view::EXTRA_VIEW_RADIUS;
+use valence_math::DVec2;
 use valence_protocol::ChunkPos;
 
 use crate::ChunkView;
 
 /// A bounding volume hierarchy for chunk positions.
+///
+/// With the `serde` feature, the BVH can be serialized to avoid rebuilding it,
+/// for example when a server restarts. Deserializing runs
+/// [`ChunkBvh::validate`] and fails if the BVH is malformed, so a corrupted
+/// BVH is never returned.
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct ChunkBvh<T, const MAX_SURFACE_AREA: i32 = { 8 * 4 }> {
     nodes: Vec<Node>,
     values: Vec<T>,
+    max_surface_area: i32,
+    max_leaf_items: usize,
+    linear_threshold: usize,
 }
 
 impl<T, const MAX_SURFACE_AREA: i32> Default for ChunkBvh<T, MAX_SURFACE_AREA> {
@@ -19,44 +33,80 @@ impl<T, const MAX_SURFACE_AREA: i32> Default for ChunkBvh<T, MAX_SURFACE_AREA> {
 }
 
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 enum Node {
     Internal {
-        bounds: Aabb,
+        bounds: ChunkAabb,
         left: NodeIdx,
         right: NodeIdx,
     },
     Leaf {
-        bounds: Aabb,
+        bounds: ChunkAabb,
         /// Range of values in the values array.
         values: Range<NodeIdx>,
     },
 }
 
-#[cfg(test)]
 impl Node {
-    fn bounds(&self) -> Aabb {
+    fn bounds(&self) -> ChunkAabb {
         match self {
-            Node::Internal { bounds, .. } => *bounds,
-            Node::Leaf { bounds, .. } => *bounds,
+            Node::Internal { bounds, .. } | Node::Leaf { bounds, .. } => *bounds,
         }
     }
 }
 
 type NodeIdx = u32;
 
+/// The maximum depth of a BVH, counting the leaves. [`ChunkBvh::build`] splits
+/// at the spatial midpoint, so it never gets close to this. Incremental
+/// inserts rebuild the BVH before they could exceed it.
+const MAX_DEPTH: usize = 256;
+
+/// An axis-aligned rectangle of chunk positions. Both corners are inclusive.
 #[derive(Copy, Clone, PartialEq, Eq, Debug)]
-struct Aabb {
-    min: ChunkPos,
-    max: ChunkPos,
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ChunkAabb {
+    #[cfg_attr(feature = "serde", serde(with = "serde_chunk_pos"))]
+    pub min: ChunkPos,
+    #[cfg_attr(feature = "serde", serde(with = "serde_chunk_pos"))]
+    pub max: ChunkPos,
 }
 
-impl Aabb {
+impl ChunkAabb {
+    /// Creates the smallest AABB containing both `a` and `b`.
+    pub fn new(a: ChunkPos, b: ChunkPos) -> Self {
+        Self::point(a).union(Self::point(b))
+    }
+
+    /// Returns whether `pos` is inside this AABB.
+    pub fn contains_point(self, pos: ChunkPos) -> bool {
+        (self.min.x..=self.max.x).contains(&pos.x) && (self.min.z..=self.max.z).contains(&pos.z)
+    }
+
+    /// Returns whether `other` is entirely inside this AABB. An AABB contains
+    /// itself.
+    pub fn contains(self, other: Self) -> bool {
+        self.contains_point(other.min) && self.contains_point(other.max)
+    }
+
+    /// Returns whether every position in this AABB is inside `view`. Since the
+    /// view is a disc, checking the corners is enough.
+    fn inside_view(self, view: ChunkView, view_aabb: Self) -> bool {
+        view_aabb.contains(self)
+            && self
+                .corners()
+                .into_iter()
+                .all(|corner| view.contains(corner))
+    }
+
     fn point(pos: ChunkPos) -> Self {
         Self { min: pos, max: pos }
     }
 
-    /// Sum of side lengths.
-    fn surface_area(self) -> i32 {
+    /// Sum of side lengths. This is the metric that the maximum surface area is
+    /// compared against. It is computed in 64 bits so that it cannot overflow
+    /// for extreme chunk positions.
+    fn surface_area(self) -> i64 {
         (self.length_x() + self.length_z()) * 2
     }
 
@@ -68,12 +118,44 @@ impl Aabb {
         }
     }
 
-    fn length_x(self) -> i32 {
-        self.max.x - self.min.x
+    fn length_x(self) -> i64 {
+        self.max.x as i64 - self.min.x as i64
+    }
+
+    fn length_z(self) -> i64 {
+        self.max.z as i64 - self.min.z as i64
+    }
+
+    /// Returns the squared Euclidean distance from `pos` to the closest
+    /// position in this AABB, or `0` if `pos` is inside it.
+    ///
+    /// This is the metric [`ChunkView`] uses, but a view reaches a couple of
+    /// chunks past its distance, so the result can't be compared with a view
+    /// distance directly. Use [`Self::intersects_view`] to check a view.
+    ///
+    /// The result saturates instead of overflowing for extreme positions.
+    pub fn distance_squared(self, pos: ChunkPos) -> i64 {
+        let closest = ChunkPos::new(
+            pos.x.clamp(self.min.x, self.max.x),
+            pos.z.clamp(self.min.z, self.max.z),
+        );
+
+        distance_squared(closest, pos)
+    }
+
+    /// Returns whether any position in this AABB is inside `view`.
+    pub fn intersects_view(self, view: ChunkView) -> bool {
+        let radius = view.dist() as i64 + EXTRA_VIEW_RADIUS as i64;
+        self.distance_squared(view.pos) <= radius * radius
     }
 
-    fn length_z(self) -> i32 {
-        self.max.z - self.min.z
+    fn corners(self) -> [ChunkPos; 4] {
+        [
+            self.min,
+            self.max,
+            ChunkPos::new(self.min.x, self.max.z),
+            ChunkPos::new(self.max.x, self.min.z),
+        ]
     }
 
     fn intersects(self, other: Self) -> bool {
@@ -84,6 +166,57 @@ impl Aabb {
     }
 }
 
+/// A vertical plane in chunk space that divides it into an inside and an
+/// outside half. A chunk position `p` is inside if `normal · p + offset >= 0`.
+///
+/// Used with [`ChunkBvh::query_frustum`].
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct ChunkPlane {
+    /// The normal of the plane as `(x, z)`, pointing toward the inside.
+    pub normal: DVec2,
+    pub offset: f64,
+}
+
+impl ChunkPlane {
+    /// Creates the plane through `point` whose inside is in the direction of
+    /// `normal`.
+    pub fn through(point: DVec2, normal: DVec2) -> Self {
+        Self {
+            normal,
+            offset: -normal.dot(point),
+        }
+    }
+
+    /// Returns the two planes bounding a horizontal view cone starting at
+    /// `origin`. `yaw` is the facing direction in degrees, as in
+    /// [`Look`](valence_entity::Look), and `fov` is the angle of the cone in
+    /// degrees. `fov` should be less than 180.
+    pub fn view_cone(origin: ChunkPos, yaw: f32, fov: f32) -> [Self; 2] {
+        let origin = DVec2::new(origin.x as f64, origin.z as f64);
+
+        // A yaw of 0 faces +Z and a yaw of 90 faces -X.
+        let facing = (yaw as f64 + 90.0).to_radians();
+        let half_fov = (fov as f64 / 2.0).to_radians();
+
+        let left = facing + half_fov - FRAC_PI_2;
+        let right = facing - half_fov + FRAC_PI_2;
+
+        [
+            Self::through(origin, DVec2::new(left.cos(), left.sin())),
+            Self::through(origin, DVec2::new(right.cos(), right.sin())),
+        ]
+    }
+
+    /// Returns whether `pos` is on the inside of the plane.
+    pub fn contains(self, pos: ChunkPos) -> bool {
+        self.signed_distance(pos) >= 0.0
+    }
+
+    fn signed_distance(self, pos: ChunkPos) -> f64 {
+        self.normal.dot(DVec2::new(pos.x as f64, pos.z as f64)) + self.offset
+    }
+}
+
 /// Obtains a chunk position for the purpose of placement in the BVH.
 pub trait GetChunkPos {
     fn chunk_pos(&self) -> ChunkPos;
@@ -102,24 +235,114 @@ impl<T, const MAX_SURFACE_AREA: i32> ChunkBvh<T, MAX_SURFACE_AREA> {
         Self {
             nodes: vec![],
             values: vec![],
+            max_surface_area: MAX_SURFACE_AREA,
+            max_leaf_items: 1,
+            linear_threshold: 0,
         }
     }
+
+    /// Sets the number of values below which the BVH doesn't build a tree at
+    /// all. All values are put in a single leaf instead, so queries become a
+    /// linear scan. For a handful of values this is faster than building and
+    /// traversing a tree.
+    ///
+    /// The default is `0`, which always builds a tree. The new threshold takes
+    /// effect on the next build.
+    pub fn with_linear_threshold(mut self, linear_threshold: usize) -> Self {
+        self.linear_threshold = linear_threshold;
+        self
+    }
+
+    /// Sets the largest surface area a leaf may have, overriding
+    /// `MAX_SURFACE_AREA` for this BVH. Smaller leaves make queries test fewer
+    /// values that turn out to be out of view, at the cost of a deeper tree.
+    /// This allows tuning each BVH for the density of its values without a
+    /// separate type per setting.
+    ///
+    /// The new limit takes effect on the next build.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max_surface_area` is not positive.
+    pub fn with_max_surface_area(mut self, max_surface_area: i32) -> Self {
+        assert!(max_surface_area > 0);
+        self.max_surface_area = max_surface_area;
+        self
+    }
+
+    /// Sets the number of values a leaf may hold regardless of its surface
+    /// area. A range of values becomes a leaf once either its surface area is
+    /// at most the maximum surface area or it holds at most `max_leaf_items`
+    /// values.
+    ///
+    /// This gives more predictable leaves for uniformly dense data. The default
+    /// is `1`, which leaves the split decision to the surface area alone. The
+    /// new limit takes effect on the next build.
+    pub fn with_max_leaf_items(mut self, max_leaf_items: usize) -> Self {
+        self.max_leaf_items = max_leaf_items;
+        self
+    }
+
+    /// Returns the number of values in the BVH.
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// Removes every value from the BVH, keeping the allocated memory for
+    /// reuse. This makes rebuilding the BVH every tick cheaper.
+    pub fn clear(&mut self) {
+        self.nodes.clear();
+        self.values.clear();
+    }
+
+    /// Reserves capacity for at least `additional` more values than the BVH
+    /// currently holds, such as before a [`Self::build`] with a known number of
+    /// values.
+    pub fn reserve(&mut self, additional: usize) {
+        self.values.reserve(additional);
+    }
+
+    /// Returns an iterator over all values in the BVH. Values are yielded in
+    /// leaf order, so values that are close together in space tend to be
+    /// close together in the iteration order.
+    pub fn iter(&self) -> impl ExactSizeIterator<Item = &T> + '_ {
+        self.values.iter()
+    }
+
+    /// Returns a mutable iterator over all values in the BVH.
+    ///
+    /// The BVH must be rebuilt if the chunk positions of any values change.
+    pub fn iter_mut(&mut self) -> impl ExactSizeIterator<Item = &mut T> + '_ {
+        self.values.iter_mut()
+    }
 }
 
 impl<T: GetChunkPos, const MAX_SURFACE_AREA: i32> ChunkBvh<T, MAX_SURFACE_AREA> {
     pub fn build(&mut self, items: impl IntoIterator<Item = T>) {
-        self.nodes.clear();
-        self.values.clear();
+        self.clear();
 
         self.values.extend(items);
 
         if let Some(bounds) = value_bounds(&self.values) {
-            self.build_rec(bounds, 0..self.values.len());
+            if self.values.len() < self.linear_threshold {
+                self.nodes.push(Node::Leaf {
+                    bounds,
+                    values: 0..self.values.len() as u32,
+                });
+            } else {
+                self.build_rec(bounds, 0..self.values.len());
+            }
         }
     }
 
-    fn build_rec(&mut self, bounds: Aabb, value_range: Range<usize>) {
-        if bounds.surface_area() <= MAX_SURFACE_AREA {
+    fn build_rec(&mut self, bounds: ChunkAabb, value_range: Range<usize>) {
+        if bounds.surface_area() <= self.max_surface_area as i64
+            || value_range.len() <= self.max_leaf_items
+        {
             self.nodes.push(Node::Leaf {
                 bounds,
                 values: value_range.start as u32..value_range.end as u32,
@@ -138,14 +361,26 @@ impl<T: GetChunkPos, const MAX_SURFACE_AREA: i32> ChunkBvh<T, MAX_SURFACE_AREA>
             // Split on Z axis.
 
             let mid = middle(bounds.min.x, bounds.max.x);
-            partition(values, |v| v.chunk_pos().x >= mid)
+            partition(values, |v| v.chunk_pos().x <= mid)
         } else {
             // Split on X axis.
 
             let mid = middle(bounds.min.z, bounds.max.z);
-            partition(values, |v| v.chunk_pos().z >= mid)
+            partition(values, |v| v.chunk_pos().z <= mid)
         };
 
+        // The midpoint is rounded down, so both halves are nonempty whenever the
+        // bounds have a nonzero length. If a half is empty anyway, keep the
+        // values in a single oversized leaf.
+        if point == 0 || point == value_range.len() {
+            self.nodes.push(Node::Leaf {
+                bounds,
+                values: value_range.start as u32..value_range.end as u32,
+            });
+
+            return;
+        }
+
         let left_range = value_range.start..value_range.start + point;
         let right_range = left_range.end..value_range.end;
 
@@ -169,13 +404,51 @@ impl<T: GetChunkPos, const MAX_SURFACE_AREA: i32> ChunkBvh<T, MAX_SURFACE_AREA>
     }
 
     pub fn query(&self, view: ChunkView, mut f: impl FnMut(&T)) {
+        self.query_indexed(view, |_, val| f(val))
+    }
+
+    /// Clears `out` and fills it with every value in `view`. Reusing the same
+    /// buffer across queries avoids allocating on every query.
+    ///
+    /// The values in `out` borrow from the BVH, so the BVH can't be rebuilt or
+    /// mutated until the buffer is cleared or dropped.
+    pub fn query_into<'a>(&'a self, view: ChunkView, out: &mut Vec<&'a T>) {
+        out.clear();
+        self.query_indexed(view, |idx, _| out.push(&self.values[idx]));
+    }
+
+    /// Like [`Self::query`], but also passes the index of each value in the
+    /// BVH's value storage, which is the position the value would have in
+    /// [`Self::iter`]. Indices are only stable until the next call to
+    /// [`Self::build`] or any other method that rebuilds the BVH.
+    pub fn query_indexed(&self, view: ChunkView, mut f: impl FnMut(usize, &T)) {
         if let Some(root) = self.nodes.last() {
             let (min, max) = view.bounding_box();
-            self.query_rec(root, view, Aabb { min, max }, &mut f);
+            self.query_rec(root, view, ChunkAabb { min, max }, &mut f);
+        }
+    }
+
+    /// Like [`Self::query`], but gives mutable access to the values in `view`.
+    ///
+    /// `f` must not change the chunk positions of the values, since the BVH
+    /// would no longer match them. Call [`Self::build`] afterwards if it does.
+    /// Other data in the values can be changed freely.
+    pub fn query_mut(&mut self, view: ChunkView, mut f: impl FnMut(&mut T)) {
+        let mut indices = vec![];
+        self.query_indexed(view, |idx, _| indices.push(idx));
+
+        for idx in indices {
+            f(&mut self.values[idx]);
         }
     }
 
-    fn query_rec(&self, node: &Node, view: ChunkView, view_aabb: Aabb, f: &mut impl FnMut(&T)) {
+    fn query_rec(
+        &self,
+        node: &Node,
+        view: ChunkView,
+        view_aabb: ChunkAabb,
+        f: &mut impl FnMut(usize, &T),
+    ) {
         match node {
             Node::Internal {
                 bounds,
@@ -188,10 +461,17 @@ impl<T: GetChunkPos, const MAX_SURFACE_AREA: i32> ChunkBvh<T, MAX_SURFACE_AREA>
                 }
             }
             Node::Leaf { bounds, values } => {
-                if bounds.intersects(view_aabb) {
-                    for val in &self.values[values.start as usize..values.end as usize] {
+                let range = values.start as usize..values.end as usize;
+
+                if bounds.inside_view(view, view_aabb) {
+                    // Every value in the leaf is in view, so skip testing them.
+                    for (idx, val) in range.clone().zip(&self.values[range]) {
+                        f(idx, val)
+                    }
+                } else if bounds.intersects(view_aabb) {
+                    for (idx, val) in range.clone().zip(&self.values[range]) {
                         if view.contains(val.chunk_pos()) {
-                            f(val)
+                            f(idx, val)
                         }
                     }
                 }
@@ -199,141 +479,1988 @@ impl<T: GetChunkPos, const MAX_SURFACE_AREA: i32> ChunkBvh<T, MAX_SURFACE_AREA>
         }
     }
 
-    pub fn shrink_to_fit(&mut self) {
-        self.nodes.shrink_to_fit();
-        self.values.shrink_to_fit();
-    }
-
-    #[cfg(test)]
-    fn check_invariants(&self) {
-        if let Some(root) = self.nodes.last() {
-            self.check_invariants_rec(root);
+    /// Returns the number of values in `view`. This is cheaper than counting
+    /// with [`Self::query`], since leaves entirely in view are counted without
+    /// visiting their values.
+    pub fn count(&self, view: ChunkView) -> usize {
+        match self.nodes.last() {
+            Some(root) => {
+                let (min, max) = view.bounding_box();
+                self.count_rec(root, view, ChunkAabb { min, max })
+            }
+            None => 0,
         }
     }
 
-    #[cfg(test)]
-    fn check_invariants_rec(&self, node: &Node) {
+    fn count_rec(&self, node: &Node, view: ChunkView, view_aabb: ChunkAabb) -> usize {
         match node {
             Node::Internal {
                 bounds,
                 left,
                 right,
             } => {
-                let left = &self.nodes[*left as usize];
-                let right = &self.nodes[*right as usize];
+                if bounds.intersects(view_aabb) {
+                    self.count_rec(&self.nodes[*left as usize], view, view_aabb)
+                        + self.count_rec(&self.nodes[*right as usize], view, view_aabb)
+                } else {
+                    0
+                }
+            }
+            Node::Leaf { bounds, values } => {
+                if bounds.inside_view(view, view_aabb) {
+                    values.len()
+                } else if bounds.intersects(view_aabb) {
+                    self.values[values.start as usize..values.end as usize]
+                        .iter()
+                        .filter(|val| view.contains(val.chunk_pos()))
+                        .count()
+                } else {
+                    0
+                }
+            }
+        }
+    }
+
+    /// Calls `f` with every value inside all of `planes`, such as the planes
+    /// of a [`ChunkPlane::view_cone`]. Unlike [`Self::query`], the region is
+    /// not bounded by a view distance unless the planes bound it.
+    pub fn query_frustum(&self, planes: &[ChunkPlane], mut f: impl FnMut(&T)) {
+        if let Some(root) = self.nodes.last() {
+            self.query_frustum_rec(root, planes, &mut f);
+        }
+    }
+
+    fn query_frustum_rec(&self, node: &Node, planes: &[ChunkPlane], f: &mut impl FnMut(&T)) {
+        let corners = node.bounds().corners();
 
-                assert_eq!(left.bounds().union(right.bounds()), *bounds);
+        // Prune nodes entirely outside any plane.
+        if planes
+            .iter()
+            .any(|plane| corners.iter().all(|&corner| !plane.contains(corner)))
+        {
+            return;
+        }
 
-                self.check_invariants_rec(left);
-                self.check_invariants_rec(right);
+        match node {
+            Node::Internal { left, right, .. } => {
+                self.query_frustum_rec(&self.nodes[*left as usize], planes, f);
+                self.query_frustum_rec(&self.nodes[*right as usize], planes, f);
             }
-            Node::Leaf {
-                bounds: leaf_bounds,
-                values,
-            } => {
-                let bounds = value_bounds(&self.values[values.start as usize..values.end as usize])
-                    .expect("leaf should be nonempty");
+            Node::Leaf { values, .. } => {
+                let values = &self.values[values.start as usize..values.end as usize];
 
-                assert_eq!(*leaf_bounds, bounds);
+                if planes
+                    .iter()
+                    .all(|plane| corners.iter().all(|&corner| plane.contains(corner)))
+                {
+                    // Every value in the leaf is inside, so skip testing them.
+                    values.iter().for_each(f);
+                } else {
+                    for val in values {
+                        let pos = val.chunk_pos();
+                        if planes.iter().all(|plane| plane.contains(pos)) {
+                            f(val)
+                        }
+                    }
+                }
             }
         }
     }
-}
 
-fn value_bounds<T: GetChunkPos>(values: &[T]) -> Option<Aabb> {
-    values
-        .iter()
-        .map(|v| Aabb::point(v.chunk_pos()))
-        .reduce(Aabb::union)
-}
+    /// Calls `f` with every value in the view of distance `outer` around
+    /// `center` that is not also in the view of distance `inner`. This is the
+    /// set of values that come into view when a client's view distance grows
+    /// from `inner` to `outer`.
+    ///
+    /// Nodes entirely inside the inner view are skipped without visiting
+    /// their values.
+    pub fn query_ring(&self, center: ChunkPos, inner: u8, outer: u8, mut f: impl FnMut(&T)) {
+        if let Some(root) = self.nodes.last() {
+            let outer = ChunkView::new(center, outer);
+            let inner = ChunkView::new(center, inner);
 
-fn middle(min: i32, max: i32) -> i32 {
-    // Cast to i64 to avoid intermediate overflow.
-    ((min as i64 + max as i64) / 2) as i32
-}
+            let (min, max) = outer.bounding_box();
+            let outer_aabb = ChunkAabb { min, max };
+            let (min, max) = inner.bounding_box();
+            let inner_aabb = ChunkAabb { min, max };
 
-/// Partitions the slice in place and returns the partition point. Why this
-/// isn't in Rust's stdlib I don't know.
-fn partition<T>(s: &mut [T], mut pred: impl FnMut(&T) -> bool) -> usize {
-    let mut it = s.iter_mut();
-    let mut true_count = 0;
+            self.query_ring_rec(root, (outer, outer_aabb), (inner, inner_aabb), &mut f);
+        }
+    }
 
-    while let Some(head) = it.find(|x| {
-        if pred(x) {
-            true_count += 1;
-            false
-        } else {
-            true
+    fn query_ring_rec(
+        &self,
+        node: &Node,
+        outer: (ChunkView, ChunkAabb),
+        inner: (ChunkView, ChunkAabb),
+        f: &mut impl FnMut(&T),
+    ) {
+        let bounds = node.bounds();
+
+        if !bounds.intersects(outer.1) || bounds.inside_view(inner.0, inner.1) {
+            return;
         }
-    }) {
-        if let Some(tail) = it.rfind(|x| pred(x)) {
-            mem::swap(head, tail);
-            true_count += 1;
-        } else {
-            break;
+
+        match node {
+            Node::Internal { left, right, .. } => {
+                self.query_ring_rec(&self.nodes[*left as usize], outer, inner, f);
+                self.query_ring_rec(&self.nodes[*right as usize], outer, inner, f);
+            }
+            Node::Leaf { values, .. } => {
+                for val in &self.values[values.start as usize..values.end as usize] {
+                    let pos = val.chunk_pos();
+                    if outer.0.contains(pos) && !inner.0.contains(pos) {
+                        f(val)
+                    }
+                }
+            }
         }
     }
-    true_count
-}
 
-#[cfg(test)]
-mod tests {
-    use rand::Rng;
+    /// Like [`Self::query`], but also counts the work done by the query. This
+    /// is useful for tuning the maximum surface area for a particular workload.
+    ///
+    /// The counting happens in a separate traversal, so [`Self::query`] has no
+    /// overhead from it.
+    pub fn query_counted(&self, view: ChunkView, mut f: impl FnMut(&T)) -> QueryCost {
+        let mut cost = QueryCost::default();
 
-    use super::*;
+        if let Some(root) = self.nodes.last() {
+            let (min, max) = view.bounding_box();
+            self.query_counted_rec(root, view, ChunkAabb { min, max }, &mut cost, &mut f);
+        }
 
-    #[test]
-    fn partition_middle() {
-        let mut arr = [2, 3, 4, 5];
-        let mid = middle(arr[0], arr[arr.len() - 1]);
+        cost
+    }
 
-        let point = partition(&mut arr, |&x| mid >= x);
+    fn query_counted_rec(
+        &self,
+        node: &Node,
+        view: ChunkView,
+        view_aabb: ChunkAabb,
+        cost: &mut QueryCost,
+        f: &mut impl FnMut(&T),
+    ) {
+        match node {
+            Node::Internal {
+                bounds,
+                left,
+                right,
+            } => {
+                cost.internal_nodes += 1;
 
-        assert_eq!(point, 2);
-        assert_eq!(&arr[..point], &[2, 3]);
-        assert_eq!(&arr[point..], &[4, 5]);
+                if bounds.intersects(view_aabb) {
+                    self.query_counted_rec(&self.nodes[*left as usize], view, view_aabb, cost, f);
+                    self.query_counted_rec(&self.nodes[*right as usize], view, view_aabb, cost, f);
+                }
+            }
+            Node::Leaf { bounds, values } => {
+                if bounds.intersects(view_aabb) {
+                    cost.leaves += 1;
+
+                    for val in &self.values[values.start as usize..values.end as usize] {
+                        if view.contains(val.chunk_pos()) {
+                            cost.values += 1;
+                            f(val)
+                        }
+                    }
+                }
+            }
+        }
     }
 
-    #[test]
-    fn query_visits_correct_nodes() {
-        let mut bvh = ChunkBvh::<ChunkPos>::new();
+    /// Returns up to `k` values within `radius` chunks of `center`, nearest
+    /// first.
+    ///
+    /// Distances are Euclidean distances between chunk positions, and a value
+    /// exactly `radius` chunks away is included. Values at the same distance
+    /// are ordered by their position in [`Self::iter`].
+    pub fn nearest_k_within(&self, center: ChunkPos, k: usize, radius: i32) -> Vec<&T> {
+        let mut heap = BinaryHeap::with_capacity(k);
 
-        let mut positions = vec![];
+        if let (Some(root), true) = (self.nodes.last(), k > 0 && radius >= 0) {
+            let radius = radius as i64;
+            self.nearest_k_rec(root, center, k, radius * radius, &mut heap);
+        }
 
-        let size = 500;
-        let mut rng = rand::thread_rng();
+        heap.into_sorted_vec()
+            .into_iter()
+            .map(|(_, idx)| &self.values[idx])
+            .collect()
+    }
 
-        // Create a bunch of positions in a large area.
-        for _ in 0..100_000 {
-            positions.push(ChunkPos {
-                x: rng.gen_range(-size / 2..size / 2),
-                z: rng.gen_range(-size / 2..size / 2),
-            });
+    fn nearest_k_rec(
+        &self,
+        node: &Node,
+        center: ChunkPos,
+        k: usize,
+        radius_squared: i64,
+        heap: &mut BinaryHeap<(i64, usize)>,
+    ) {
+        let dist = node.bounds().distance_squared(center);
+
+        if dist > radius_squared || heap.len() == k && heap.peek().is_some_and(|&(d, _)| dist > d) {
+            return;
         }
 
-        // Put the view in the center of that area.
-        let view = ChunkView::new(ChunkPos::default(), 32);
+        match node {
+            Node::Internal { left, right, .. } => {
+                self.nearest_k_rec(&self.nodes[*left as usize], center, k, radius_squared, heap);
+                self.nearest_k_rec(
+                    &self.nodes[*right as usize],
+                    center,
+                    k,
+                    radius_squared,
+                    heap,
+                );
+            }
+            Node::Leaf { values, .. } => {
+                for idx in values.start as usize..values.end as usize {
+                    let dist = distance_squared(self.values[idx].chunk_pos(), center);
 
-        let mut viewed_positions = vec![];
+                    if dist > radius_squared {
+                        continue;
+                    }
 
-        // Create a list of positions the view contains.
-        for &pos in &positions {
-            if view.contains(pos) {
-                viewed_positions.push(pos);
+                    if heap.len() < k {
+                        heap.push((dist, idx));
+                    } else if heap.peek().is_some_and(|&top| (dist, idx) < top) {
+                        heap.pop();
+                        heap.push((dist, idx));
+                    }
+                }
             }
         }
+    }
 
-        bvh.build(positions);
+    /// Returns the value nearest to `pos`, or `None` if the BVH is empty.
+    ///
+    /// Distances are Euclidean distances between chunk positions. Of the
+    /// values at the same distance, the first in [`Self::iter`] is returned.
+    pub fn nearest(&self, pos: ChunkPos) -> Option<&T> {
+        self.nearest_impl(pos, i64::MAX)
+    }
 
-        bvh.check_invariants();
+    /// Like [`Self::nearest`], but only considers values within `radius`
+    /// chunks of `pos`. A value exactly `radius` chunks away is included.
+    pub fn nearest_within(&self, pos: ChunkPos, radius: i32) -> Option<&T> {
+        if radius < 0 {
+            return None;
+        }
 
-        // Check that we query exactly the positions that we know the view can see.
+        let radius = radius as i64;
+        self.nearest_impl(pos, radius * radius)
+    }
 
-        bvh.query(view, |pos| {
-            let idx = viewed_positions.iter().position(|p| p == pos).expect("😔");
-            viewed_positions.remove(idx);
-        });
+    /// Best-first search for the nearest value, visiting nodes in order of
+    /// their distance to `pos` and stopping once no remaining node can hold a
+    /// value closer than the best one found.
+    fn nearest_impl(&self, pos: ChunkPos, max_distance_squared: i64) -> Option<&T> {
+        let root = self.nodes.len().checked_sub(1)?;
 
-        assert!(viewed_positions.is_empty());
+        let mut best: Option<(i64, usize)> = None;
+        let mut heap = BinaryHeap::new();
+        heap.push(Reverse((
+            self.nodes[root].bounds().distance_squared(pos),
+            root,
+        )));
+
+        while let Some(Reverse((dist, node_idx))) = heap.pop() {
+            // Nodes at the same distance as the best value may still hold a
+            // value that comes first.
+            if dist > max_distance_squared || best.is_some_and(|(d, _)| dist > d) {
+                break;
+            }
+
+            match &self.nodes[node_idx] {
+                Node::Internal { left, right, .. } => {
+                    for child in [*left as usize, *right as usize] {
+                        let dist = self.nodes[child].bounds().distance_squared(pos);
+                        let pruned = match best {
+                            Some((best_dist, _)) => dist > best_dist,
+                            None => false,
+                        };
+
+                        if dist <= max_distance_squared && !pruned {
+                            heap.push(Reverse((dist, child)));
+                        }
+                    }
+                }
+                Node::Leaf { values, .. } => {
+                    for idx in values.start as usize..values.end as usize {
+                        let dist = distance_squared(self.values[idx].chunk_pos(), pos);
+                        let closer = match best {
+                            Some(best) => (dist, idx) < best,
+                            None => true,
+                        };
+
+                        if dist <= max_distance_squared && closer {
+                            best = Some((dist, idx));
+                        }
+                    }
+                }
+            }
+        }
+
+        best.map(|(_, idx)| &self.values[idx])
+    }
+
+    /// Like [`Self::query`], but gives up after `budget` values have been
+    /// visited. A value counts as visited when it is tested against the view,
+    /// regardless of whether it is contained in it.
+    ///
+    /// Returns `true` if the query completed, or `false` if it was cut short
+    /// because the budget ran out. This bounds the amount of work done on
+    /// pathologically dense regions.
+    pub fn query_budget(&self, view: ChunkView, budget: usize, mut f: impl FnMut(&T)) -> bool {
+        if let Some(root) = self.nodes.last() {
+            let (min, max) = view.bounding_box();
+            let mut remaining = budget;
+            self.query_budget_rec(root, view, ChunkAabb { min, max }, &mut remaining, &mut f)
+        } else {
+            true
+        }
+    }
+
+    fn query_budget_rec(
+        &self,
+        node: &Node,
+        view: ChunkView,
+        view_aabb: ChunkAabb,
+        remaining: &mut usize,
+        f: &mut impl FnMut(&T),
+    ) -> bool {
+        match node {
+            Node::Internal {
+                bounds,
+                left,
+                right,
+            } => {
+                !bounds.intersects(view_aabb)
+                    || (self.query_budget_rec(
+                        &self.nodes[*left as usize],
+                        view,
+                        view_aabb,
+                        remaining,
+                        f,
+                    ) && self.query_budget_rec(
+                        &self.nodes[*right as usize],
+                        view,
+                        view_aabb,
+                        remaining,
+                        f,
+                    ))
+            }
+            Node::Leaf { bounds, values } => {
+                if bounds.intersects(view_aabb) {
+                    for val in &self.values[values.start as usize..values.end as usize] {
+                        if *remaining == 0 {
+                            return false;
+                        }
+
+                        *remaining -= 1;
+
+                        if view.contains(val.chunk_pos()) {
+                            f(val)
+                        }
+                    }
+                }
+
+                true
+            }
+        }
+    }
+
+    /// Removes and returns all values whose chunk position is inside
+    /// `region`. See [`Self::remove`].
+    pub fn remove_in(&mut self, region: ChunkAabb) -> Vec<T> {
+        self.remove(|val| region.contains_point(val.chunk_pos()))
+    }
+
+    /// Inserts a value without rebuilding the whole BVH.
+    ///
+    /// The value is added to the leaf whose bounds grow the least, and the
+    /// bounds of the nodes above it are refit. Once the leaf is bigger than
+    /// [`Self::build`] would make it, only that leaf is split.
+    ///
+    /// Values are stored contiguously by leaf, so making room for the value
+    /// still costs O(n). Building a BVH from many values at once with
+    /// [`Self::build`] is faster.
+    pub fn insert(&mut self, value: T) {
+        let Some(root) = self.nodes.len().checked_sub(1) else {
+            self.build([value]);
+            return;
+        };
+
+        let mut path = vec![];
+        let leaf = self.insert_rec(root, value.chunk_pos(), &mut path);
+
+        let Node::Leaf { bounds, values } = &mut self.nodes[leaf] else {
+            unreachable!("insertion should end at a leaf")
+        };
+
+        let at = values.end;
+        values.end += 1;
+        let bounds = *bounds;
+        let range = values.start as usize..values.end as usize;
+
+        self.values.insert(at as usize, value);
+
+        // Make room in the value ranges of the leaves after the insertion point.
+        for (idx, node) in self.nodes.iter_mut().enumerate() {
+            if let Node::Leaf { values, .. } = node {
+                if idx != leaf && values.start >= at {
+                    values.start += 1;
+                    values.end += 1;
+                }
+            }
+        }
+
+        if self.values.len() >= self.linear_threshold
+            && bounds.surface_area() > self.max_surface_area as i64
+            && range.len() > self.max_leaf_items
+        {
+            // Splitting a leaf can only deepen the tree, so rebuild once the
+            // leaf is too deep to keep the recursive queries bounded.
+            if path.len() >= MAX_DEPTH / 2 {
+                self.rebuild();
+            } else {
+                self.split_leaf(leaf, bounds, range, &path);
+            }
+        }
+    }
+
+    /// Grows the bounds on the path to the leaf that `pos` is inserted into,
+    /// and returns the index of that leaf. The internal nodes on the path are
+    /// pushed to `path`, starting from `idx`.
+    fn insert_rec(&mut self, idx: usize, pos: ChunkPos, path: &mut Vec<usize>) -> usize {
+        match &mut self.nodes[idx] {
+            Node::Internal {
+                bounds,
+                left,
+                right,
+            } => {
+                *bounds = bounds.union(ChunkAabb::point(pos));
+                let (left, right) = (*left as usize, *right as usize);
+                path.push(idx);
+
+                // Prefer the child that grows the least, then the smaller one.
+                let cost = |node: &Node| {
+                    let bounds = node.bounds();
+                    let grown = bounds.union(ChunkAabb::point(pos)).surface_area();
+                    (grown - bounds.surface_area(), grown)
+                };
+
+                if cost(&self.nodes[left]) <= cost(&self.nodes[right]) {
+                    self.insert_rec(left, pos, path)
+                } else {
+                    self.insert_rec(right, pos, path)
+                }
+            }
+            Node::Leaf { bounds, .. } => {
+                *bounds = bounds.union(ChunkAabb::point(pos));
+                idx
+            }
+        }
+    }
+
+    /// Replaces `leaf`, which is reached through the internal nodes in `path`,
+    /// with a subtree built from its values.
+    ///
+    /// Children must come before their parents, so the subtree and copies of
+    /// the nodes on `path` are pushed to the end, and the nodes they replace
+    /// are removed afterwards.
+    fn split_leaf(&mut self, leaf: usize, bounds: ChunkAabb, range: Range<usize>, path: &[usize]) {
+        let mut old_child = leaf as NodeIdx;
+
+        let len = self.nodes.len();
+        self.build_rec(bounds, range);
+
+        // The values could not be split, so the leaf stays oversized.
+        if self.nodes.len() == len + 1 {
+            self.nodes.pop();
+            return;
+        }
+
+        let mut new_child = (self.nodes.len() - 1) as NodeIdx;
+
+        for &idx in path.iter().rev() {
+            let Node::Internal {
+                bounds,
+                left,
+                right,
+            } = self.nodes[idx].clone()
+            else {
+                unreachable!("path should only hold internal nodes")
+            };
+
+            let (left, right) = if left == old_child {
+                (new_child, right)
+            } else {
+                (left, new_child)
+            };
+
+            self.nodes.push(Node::Internal {
+                bounds,
+                left,
+                right,
+            });
+
+            old_child = idx as NodeIdx;
+            new_child = (self.nodes.len() - 1) as NodeIdx;
+        }
+
+        self.compact();
+    }
+
+    /// Removes and returns all values for which `pred` returns `true`,
+    /// without rebuilding the whole BVH.
+    ///
+    /// The bounds of the remaining nodes are refit, and internal nodes left
+    /// with a single nonempty child are replaced by that child. The BVH is
+    /// rebuilt if most of its nodes end up unused.
+    pub fn remove(&mut self, mut pred: impl FnMut(&T) -> bool) -> Vec<T> {
+        // The number of kept values before each index, used to move the value
+        // ranges of the leaves.
+        let mut kept_before = Vec::with_capacity(self.values.len() + 1);
+        let mut removed = vec![];
+        let mut kept = Vec::with_capacity(self.values.len());
+
+        for val in mem::take(&mut self.values) {
+            kept_before.push(kept.len() as NodeIdx);
+
+            if pred(&val) {
+                removed.push(val);
+            } else {
+                kept.push(val);
+            }
+        }
+
+        kept_before.push(kept.len() as NodeIdx);
+        self.values = kept;
+
+        if removed.is_empty() {
+            return removed;
+        }
+
+        for node in &mut self.nodes {
+            if let Node::Leaf { values, .. } = node {
+                if let (Some(&start), Some(&end)) = (
+                    kept_before.get(values.start as usize),
+                    kept_before.get(values.end as usize),
+                ) {
+                    *values = start..end;
+                }
+            }
+        }
+
+        let Some(root) = self.nodes.len().checked_sub(1) else {
+            return removed;
+        };
+
+        let mut live_nodes = 0;
+        if self.refit_rec(root, &mut live_nodes).is_none() {
+            self.nodes.clear();
+        } else if live_nodes * 2 < self.nodes.len() {
+            self.rebuild();
+        } else {
+            self.compact();
+        }
+
+        removed
+    }
+
+    /// Refits the bounds of the subtree at `idx` after values were removed,
+    /// and returns the new bounds, or `None` if the subtree is now empty.
+    /// Nodes that are no longer reachable are left in place until
+    /// [`Self::compact`].
+    fn refit_rec(&mut self, idx: usize, live_nodes: &mut usize) -> Option<ChunkAabb> {
+        match self.nodes[idx].clone() {
+            Node::Internal { left, right, .. } => {
+                let left_bounds = self.refit_rec(left as usize, live_nodes);
+                let right_bounds = self.refit_rec(right as usize, live_nodes);
+
+                match (left_bounds, right_bounds) {
+                    (Some(left_bounds), Some(right_bounds)) => {
+                        let bounds = left_bounds.union(right_bounds);
+                        self.nodes[idx] = Node::Internal {
+                            bounds,
+                            left,
+                            right,
+                        };
+                        *live_nodes += 1;
+                        Some(bounds)
+                    }
+                    // The child comes before this node, so its own children
+                    // still do too.
+                    (Some(bounds), None) => {
+                        self.nodes[idx] = self.nodes[left as usize].clone();
+                        Some(bounds)
+                    }
+                    (None, Some(bounds)) => {
+                        self.nodes[idx] = self.nodes[right as usize].clone();
+                        Some(bounds)
+                    }
+                    (None, None) => None,
+                }
+            }
+            Node::Leaf { values, .. } => {
+                let bounds =
+                    value_bounds(&self.values[values.start as usize..values.end as usize])?;
+                self.nodes[idx] = Node::Leaf { bounds, values };
+                *live_nodes += 1;
+                Some(bounds)
+            }
+        }
+    }
+
+    /// Removes the nodes that are not reachable from the root, keeping
+    /// children before their parents.
+    fn compact(&mut self) {
+        let Some(root) = self.nodes.len().checked_sub(1) else {
+            return;
+        };
+
+        // Parents come after their children, so a single backwards pass marks
+        // every reachable node.
+        let mut reachable = vec![false; self.nodes.len()];
+        reachable[root] = true;
+
+        for idx in (0..self.nodes.len()).rev() {
+            if let (true, Node::Internal { left, right, .. }) = (reachable[idx], &self.nodes[idx]) {
+                reachable[*left as usize] = true;
+                reachable[*right as usize] = true;
+            }
+        }
+
+        let mut new_indices = Vec::with_capacity(self.nodes.len());
+        let mut next = 0;
+        for &reachable in &reachable {
+            new_indices.push(next);
+            next += reachable as NodeIdx;
+        }
+
+        let mut reachable = reachable.into_iter();
+        self.nodes.retain(|_| reachable.next() == Some(true));
+
+        for node in &mut self.nodes {
+            if let Node::Internal { left, right, .. } = node {
+                *left = new_indices[*left as usize];
+                *right = new_indices[*right as usize];
+            }
+        }
+    }
+
+    fn rebuild(&mut self) {
+        let values = mem::take(&mut self.values);
+        self.build(values);
+    }
+
+    pub fn shrink_to_fit(&mut self) {
+        self.nodes.shrink_to_fit();
+        self.values.shrink_to_fit();
+    }
+
+    /// Checks the structure of the BVH. Every internal node's bounds must be
+    /// the union of its children's bounds, and every leaf's bounds must be the
+    /// tight bounds of the values it contains.
+    ///
+    /// This is always true for a BVH returned by [`Self::build`], but can be
+    /// useful to catch corruption in debug builds, such as values whose chunk
+    /// positions were changed through [`Self::iter_mut`] without a rebuild.
+    ///
+    /// Child indices must be less than the index of their parent, which also
+    /// keeps them in bounds of the node array. Every node must be reached from
+    /// the root exactly once, the tree must be at most 256 levels deep, and
+    /// the leaves must cover every value exactly once. This runs in linear
+    /// time without recursing, so it is safe to call on untrusted input.
+    pub fn validate(&self) -> Result<(), BvhError> {
+        let Some(root) = self.nodes.len().checked_sub(1) else {
+            return if self.values.is_empty() {
+                Ok(())
+            } else {
+                Err(BvhError::UncoveredValues)
+            };
+        };
+
+        // Children always come before their parent, so visiting the nodes in
+        // order checks every child before its parent.
+        let mut has_parent = vec![false; self.nodes.len()];
+        let mut depths: Vec<usize> = Vec::with_capacity(self.nodes.len());
+        let mut leaves = vec![];
+
+        for (idx, node) in self.nodes.iter().enumerate() {
+            let depth = match node {
+                Node::Internal {
+                    bounds,
+                    left,
+                    right,
+                } => {
+                    let (left, right) = (*left as usize, *right as usize);
+                    if left >= idx || right >= idx {
+                        return Err(BvhError::InvalidIndex { node: idx });
+                    }
+
+                    for child in [left, right] {
+                        if mem::replace(&mut has_parent[child], true) {
+                            return Err(BvhError::SharedNode { node: child });
+                        }
+                    }
+
+                    if self.nodes[left].bounds().union(self.nodes[right].bounds()) != *bounds {
+                        return Err(BvhError::InternalBounds { node: idx });
+                    }
+
+                    depths[left].max(depths[right]) + 1
+                }
+                Node::Leaf {
+                    bounds: leaf_bounds,
+                    values,
+                } => {
+                    let leaf_values = self
+                        .values
+                        .get(values.start as usize..values.end as usize)
+                        .ok_or(BvhError::InvalidIndex { node: idx })?;
+
+                    let bounds =
+                        value_bounds(leaf_values).ok_or(BvhError::EmptyLeaf { node: idx })?;
+
+                    if bounds != *leaf_bounds {
+                        return Err(BvhError::LeafBounds { node: idx });
+                    }
+
+                    leaves.push(values.clone());
+
+                    1
+                }
+            };
+
+            if depth > MAX_DEPTH {
+                return Err(BvhError::TooDeep { node: idx });
+            }
+
+            depths.push(depth);
+        }
+
+        // The root is the only node without a parent.
+        if let Some(node) = has_parent[..root].iter().position(|&reached| !reached) {
+            return Err(BvhError::UnreachableNode { node });
+        }
+
+        leaves.sort_unstable_by_key(|values| values.start);
+
+        let mut end = 0;
+        for values in leaves {
+            if values.start != end {
+                return Err(BvhError::UncoveredValues);
+            }
+            end = values.end;
+        }
+
+        if end as usize != self.values.len() {
+            return Err(BvhError::UncoveredValues);
+        }
+
+        Ok(())
+    }
+
+    #[cfg(test)]
+    fn check_invariants(&self) {
+        if let Err(e) = self.validate() {
+            panic!("{e}");
+        }
+    }
+}
+
+/// The work done by a query, as returned by [`ChunkBvh::query_counted`].
+#[derive(Copy, Clone, PartialEq, Eq, Default, Debug)]
+pub struct QueryCost {
+    /// The number of internal nodes visited.
+    pub internal_nodes: usize,
+    /// The number of leaves whose bounds intersected the view, and therefore
+    /// had all of their values tested.
+    pub leaves: usize,
+    /// The number of values contained in the view.
+    pub values: usize,
+}
+
+/// The error returned by [`ChunkBvh::validate`] when the BVH is malformed.
+/// `node` is the index of the offending node.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum BvhError {
+    /// The node refers to a child node or range of values that does not
+    /// exist.
+    InvalidIndex { node: usize },
+    /// The bounds of an internal node are not the union of its children's
+    /// bounds.
+    InternalBounds { node: usize },
+    /// A leaf node contains no values.
+    EmptyLeaf { node: usize },
+    /// The bounds of a leaf node do not match the values it contains.
+    LeafBounds { node: usize },
+    /// The node is the child of more than one internal node, or both
+    /// children of the same one.
+    SharedNode { node: usize },
+    /// The node cannot be reached from the root.
+    UnreachableNode { node: usize },
+    /// The node is more than 256 levels above its deepest leaf.
+    TooDeep { node: usize },
+    /// Some values are in no leaf, or in more than one.
+    UncoveredValues,
+}
+
+impl fmt::Display for BvhError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BvhError::InvalidIndex { node } => write!(f, "node {node} has an invalid index"),
+            BvhError::InternalBounds { node } => write!(
+                f,
+                "bounds of internal node {node} are not the union of its children"
+            ),
+            BvhError::EmptyLeaf { node } => write!(f, "leaf node {node} is empty"),
+            BvhError::LeafBounds { node } => {
+                write!(f, "bounds of leaf node {node} do not match its values")
+            }
+            BvhError::SharedNode { node } => write!(f, "node {node} has more than one parent"),
+            BvhError::UnreachableNode { node } => {
+                write!(f, "node {node} is not reachable from the root")
+            }
+            BvhError::TooDeep { node } => {
+                write!(f, "node {node} exceeds the maximum depth of {MAX_DEPTH}")
+            }
+            BvhError::UncoveredValues => {
+                write!(f, "leaves do not cover every value exactly once")
+            }
+        }
+    }
+}
+
+impl std::error::Error for BvhError {}
+
+#[cfg(feature = "serde")]
+impl<'de, T, const MAX_SURFACE_AREA: i32> serde::Deserialize<'de> for ChunkBvh<T, MAX_SURFACE_AREA>
+where
+    T: GetChunkPos + serde::Deserialize<'de>,
+{
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use serde::de::Error;
+
+        #[derive(serde::Deserialize)]
+        #[serde(rename = "ChunkBvh")]
+        struct Repr<T> {
+            nodes: Vec<Node>,
+            values: Vec<T>,
+            max_surface_area: i32,
+            max_leaf_items: usize,
+            linear_threshold: usize,
+        }
+
+        let repr = Repr::deserialize(deserializer)?;
+
+        if repr.max_surface_area <= 0 {
+            return Err(D::Error::custom("max surface area is not positive"));
+        }
+
+        let bvh = Self {
+            nodes: repr.nodes,
+            values: repr.values,
+            max_surface_area: repr.max_surface_area,
+            max_leaf_items: repr.max_leaf_items,
+            linear_threshold: repr.linear_threshold,
+        };
+
+        bvh.validate().map_err(D::Error::custom)?;
+
+        Ok(bvh)
+    }
+}
+
+/// Serializes a [`ChunkPos`] as an `[x, z]` pair.
+#[cfg(feature = "serde")]
+mod serde_chunk_pos {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use valence_protocol::ChunkPos;
+
+    pub(super) fn serialize<S: Serializer>(
+        pos: &ChunkPos,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        [pos.x, pos.z].serialize(serializer)
+    }
+
+    pub(super) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<ChunkPos, D::Error> {
+        let [x, z] = <[i32; 2]>::deserialize(deserializer)?;
+        Ok(ChunkPos::new(x, z))
+    }
+}
+
+fn value_bounds<T: GetChunkPos>(values: &[T]) -> Option<ChunkAabb> {
+    values
+        .iter()
+        .map(|v| ChunkAabb::point(v.chunk_pos()))
+        .reduce(ChunkAabb::union)
+}
+
+/// Squared Euclidean distance between two chunk positions. Saturates instead
+/// of overflowing for extreme positions.
+fn distance_squared(a: ChunkPos, b: ChunkPos) -> i64 {
+    let dx = a.x as i64 - b.x as i64;
+    let dz = a.z as i64 - b.z as i64;
+
+    dx.saturating_mul(dx).saturating_add(dz.saturating_mul(dz))
+}
+
+fn middle(min: i32, max: i32) -> i32 {
+    // Cast to i64 to avoid intermediate overflow. Round down, so that the
+    // midpoint is always less than `max` when `min < max`.
+    (min as i64 + max as i64).div_euclid(2) as i32
+}
+
+/// Partitions the slice in place and returns the partition point. Why this
+/// isn't in Rust's stdlib I don't know.
+fn partition<T>(s: &mut [T], mut pred: impl FnMut(&T) -> bool) -> usize {
+    let mut it = s.iter_mut();
+    let mut true_count = 0;
+
+    while let Some(head) = it.find(|x| {
+        if pred(x) {
+            true_count += 1;
+            false
+        } else {
+            true
+        }
+    }) {
+        if let Some(tail) = it.rfind(|x| pred(x)) {
+            mem::swap(head, tail);
+            true_count += 1;
+        } else {
+            break;
+        }
+    }
+    true_count
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ptr;
+
+    use rand::Rng;
+
+    use super::*;
+
+    #[test]
+    fn partition_middle() {
+        let mut arr = [2, 3, 4, 5];
+        let mid = middle(arr[0], arr[arr.len() - 1]);
+
+        let point = partition(&mut arr, |&x| mid >= x);
+
+        assert_eq!(point, 2);
+        assert_eq!(&arr[..point], &[2, 3]);
+        assert_eq!(&arr[point..], &[4, 5]);
+
+        assert_eq!(middle(-1, 0), -1);
+        assert_eq!(middle(i32::MIN, i32::MAX), -1);
+    }
+
+    #[test]
+    fn small_max_surface_area_splits_adjacent_chunks() {
+        for positions in [
+            [ChunkPos::new(0, 0), ChunkPos::new(1, 0)],
+            [ChunkPos::new(-1, 0), ChunkPos::new(0, 0)],
+            [ChunkPos::new(0, -1), ChunkPos::new(0, 0)],
+        ] {
+            let mut bvh = ChunkBvh::<ChunkPos>::new().with_max_surface_area(1);
+            bvh.build(positions);
+
+            bvh.check_invariants();
+
+            let leaves = bvh
+                .nodes
+                .iter()
+                .filter(|node| matches!(node, Node::Leaf { .. }))
+                .count();
+            assert_eq!(leaves, 2);
+        }
+    }
+
+    #[test]
+    fn extreme_lengths_do_not_overflow() {
+        let aabb = ChunkAabb {
+            min: ChunkPos::new(i32::MIN, i32::MIN),
+            max: ChunkPos::new(i32::MAX, i32::MAX),
+        };
+
+        assert_eq!(aabb.length_x(), u32::MAX as i64);
+        assert_eq!(aabb.length_z(), u32::MAX as i64);
+        assert_eq!(aabb.surface_area(), u32::MAX as i64 * 4);
+
+        let mut bvh = ChunkBvh::<ChunkPos>::new();
+
+        bvh.build([
+            ChunkPos::new(i32::MIN, i32::MIN),
+            ChunkPos::new(i32::MAX, i32::MAX),
+            ChunkPos::new(i32::MIN, i32::MAX),
+            ChunkPos::new(0, 0),
+        ]);
+
+        bvh.check_invariants();
+        assert_eq!(bvh.len(), 4);
+    }
+
+    #[test]
+    fn query_visits_correct_nodes() {
+        let mut bvh = ChunkBvh::<ChunkPos>::new();
+
+        let mut positions = vec![];
+
+        let size = 500;
+        let mut rng = rand::thread_rng();
+
+        // Create a bunch of positions in a large area.
+        for _ in 0..100_000 {
+            positions.push(ChunkPos {
+                x: rng.gen_range(-size / 2..size / 2),
+                z: rng.gen_range(-size / 2..size / 2),
+            });
+        }
+
+        // Put the view in the center of that area.
+        let view = ChunkView::new(ChunkPos::default(), 32);
+
+        let mut viewed_positions = vec![];
+
+        // Create a list of positions the view contains.
+        for &pos in &positions {
+            if view.contains(pos) {
+                viewed_positions.push(pos);
+            }
+        }
+
+        bvh.build(positions);
+
+        bvh.check_invariants();
+
+        // Check that we query exactly the positions that we know the view can see.
+
+        bvh.query(view, |pos| {
+            let idx = viewed_positions.iter().position(|p| p == pos).expect("😔");
+            viewed_positions.remove(idx);
+        });
+
+        assert!(viewed_positions.is_empty());
+    }
+
+    #[test]
+    fn iter_visits_all_values() {
+        let mut bvh = ChunkBvh::<ChunkPos>::new();
+
+        let mut rng = rand::thread_rng();
+        let positions: Vec<_> = (0..1000)
+            .map(|_| ChunkPos::new(rng.gen_range(-100..100), rng.gen_range(-100..100)))
+            .collect();
+
+        bvh.build(positions.iter().copied());
+
+        assert_eq!(bvh.iter().count(), bvh.len());
+        assert_eq!(bvh.len(), positions.len());
+
+        for pos in &positions {
+            assert!(bvh.iter().any(|p| p == pos));
+        }
+    }
+
+    #[test]
+    fn remove_in_region() {
+        let mut bvh = ChunkBvh::<ChunkPos>::new();
+
+        bvh.build((0..400).map(|i| ChunkPos::new(i % 20, i / 20)));
+
+        let region = ChunkAabb::new(ChunkPos::new(9, 9), ChunkPos::new(5, 5));
+
+        let mut removed = bvh.remove_in(region);
+        removed.sort_by_key(|pos| (pos.x, pos.z));
+
+        let expected: Vec<_> = (5..=9)
+            .flat_map(|x| (5..=9).map(move |z| ChunkPos::new(x, z)))
+            .collect();
+
+        assert_eq!(removed, expected);
+        assert_eq!(bvh.len(), 400 - 25);
+        bvh.check_invariants();
+
+        // Nothing inside the region is found anymore.
+        bvh.query(ChunkView::new(ChunkPos::new(7, 7), 2), |pos| {
+            assert!(!region.contains_point(*pos), "{pos:?} was not removed");
+        });
+
+        // Queries outside the region are unaffected.
+        let view = ChunkView::new(ChunkPos::new(15, 15), 2);
+        let mut count = 0;
+        bvh.query(view, |_| count += 1);
+        assert_eq!(
+            count,
+            (0..400)
+                .filter(|i| view.contains(ChunkPos::new(i % 20, i / 20)))
+                .count()
+        );
+    }
+
+    #[test]
+    fn query_indexed_reports_value_indices() {
+        let mut bvh = ChunkBvh::<ChunkPos>::new();
+
+        bvh.build((0..400).map(|i| ChunkPos::new(i % 20, i / 20)));
+
+        let values: Vec<_> = bvh.iter().copied().collect();
+        let view = ChunkView::new(ChunkPos::new(10, 10), 4);
+
+        let mut count = 0;
+        bvh.query_indexed(view, |idx, pos| {
+            assert_eq!(values[idx], *pos);
+            count += 1;
+        });
+
+        assert_eq!(count, values.iter().filter(|&&p| view.contains(p)).count());
+    }
+
+    #[test]
+    fn query_counted_counts_work() {
+        let mut bvh = ChunkBvh::<ChunkPos>::new();
+
+        // Two clusters far apart, so the tree is a root with two leaves.
+        bvh.build([
+            ChunkPos::new(0, 0),
+            ChunkPos::new(1, 1),
+            ChunkPos::new(100, 0),
+            ChunkPos::new(101, 1),
+        ]);
+
+        assert_eq!(bvh.nodes.len(), 3);
+
+        let mut found = vec![];
+        let cost = bvh.query_counted(ChunkView::new(ChunkPos::new(0, 0), 4), |pos| {
+            found.push(*pos)
+        });
+
+        found.sort_by_key(|pos| pos.x);
+        assert_eq!(found, [ChunkPos::new(0, 0), ChunkPos::new(1, 1)]);
+
+        assert_eq!(
+            cost,
+            QueryCost {
+                internal_nodes: 1,
+                leaves: 1,
+                values: 2,
+            }
+        );
+    }
+
+    #[test]
+    fn nearest_k_within_matches_brute_force() {
+        let mut bvh = ChunkBvh::<ChunkPos>::new();
+
+        let mut rng = rand::thread_rng();
+        bvh.build((0..2000).map(|_| ChunkPos::new(rng.gen_range(-50..50), rng.gen_range(-50..50))));
+
+        for _ in 0..20 {
+            let center = ChunkPos::new(rng.gen_range(-60..60), rng.gen_range(-60..60));
+            let k = rng.gen_range(0..30);
+            let radius = rng.gen_range(0..40);
+
+            let mut expected: Vec<_> = bvh
+                .iter()
+                .enumerate()
+                .map(|(idx, pos)| (distance_squared(*pos, center), idx, pos))
+                .filter(|&(dist, _, _)| dist <= radius as i64 * radius as i64)
+                .collect();
+
+            expected.sort();
+            expected.truncate(k);
+
+            let expected: Vec<_> = expected.into_iter().map(|(_, _, pos)| pos).collect();
+
+            assert_eq!(bvh.nearest_k_within(center, k, radius), expected);
+        }
+    }
+
+    #[test]
+    fn nearest_matches_brute_force() {
+        let mut bvh = ChunkBvh::<ChunkPos>::new().with_max_leaf_items(4);
+
+        assert_eq!(bvh.nearest(ChunkPos::new(0, 0)), None);
+
+        let mut rng = rand::thread_rng();
+        bvh.build((0..2000).map(|_| ChunkPos::new(rng.gen_range(-50..50), rng.gen_range(-50..50))));
+
+        for _ in 0..50 {
+            let pos = ChunkPos::new(rng.gen_range(-60..60), rng.gen_range(-60..60));
+            let radius = rng.gen_range(0..10);
+
+            let brute_force = |max_dist: i64| {
+                bvh.iter()
+                    .enumerate()
+                    .map(|(idx, value)| (distance_squared(*value, pos), idx))
+                    .filter(|&(dist, _)| dist <= max_dist)
+                    .min()
+                    .map(|(_, idx)| idx)
+            };
+
+            let index_of = |value: Option<&ChunkPos>| {
+                value.map(|v| bvh.iter().position(|w| ptr::eq(v, w)).unwrap())
+            };
+
+            assert_eq!(index_of(bvh.nearest(pos)), brute_force(i64::MAX));
+            assert_eq!(
+                index_of(bvh.nearest_within(pos, radius)),
+                brute_force(radius as i64 * radius as i64)
+            );
+        }
+
+        assert_eq!(bvh.nearest_within(ChunkPos::new(0, 0), -1), None);
+        assert_eq!(bvh.nearest_within(ChunkPos::new(1000, 1000), 10), None);
+    }
+
+    #[test]
+    fn incremental_insert_and_remove() {
+        let mut bvh = ChunkBvh::<ChunkPos>::new().with_max_leaf_items(4);
+        let mut expected = vec![];
+
+        let mut rng = rand::thread_rng();
+
+        for i in 0..2000 {
+            if i % 3 == 2 {
+                let x = rng.gen_range(-30..30);
+                let mut removed = bvh.remove(|pos| pos.x == x);
+                let mut expected_removed = expected.clone();
+                expected_removed.retain(|pos: &ChunkPos| pos.x == x);
+                expected.retain(|pos: &ChunkPos| pos.x != x);
+
+                removed.sort_by_key(|pos| (pos.x, pos.z));
+                expected_removed.sort_by_key(|pos| (pos.x, pos.z));
+                assert_eq!(removed, expected_removed);
+            } else {
+                let pos = ChunkPos::new(rng.gen_range(-30..30), rng.gen_range(-30..30));
+                bvh.insert(pos);
+                expected.push(pos);
+            }
+
+            bvh.check_invariants();
+            assert_eq!(bvh.len(), expected.len());
+        }
+
+        for _ in 0..20 {
+            let view = ChunkView::new(
+                ChunkPos::new(rng.gen_range(-40..40), rng.gen_range(-40..40)),
+                rng.gen_range(0..10),
+            );
+
+            let mut found = vec![];
+            bvh.query(view, |pos| found.push(*pos));
+            found.sort_by_key(|pos| (pos.x, pos.z));
+
+            let mut in_view: Vec<_> = expected
+                .iter()
+                .copied()
+                .filter(|pos| view.contains(*pos))
+                .collect();
+            in_view.sort_by_key(|pos| (pos.x, pos.z));
+
+            assert_eq!(found, in_view);
+        }
+
+        bvh.remove(|_| true);
+        assert!(bvh.is_empty());
+        bvh.check_invariants();
+        assert!(bvh.nodes.is_empty());
+
+        bvh.insert(ChunkPos::new(1, 2));
+        bvh.check_invariants();
+        assert_eq!(bvh.nearest(ChunkPos::new(0, 0)), Some(&ChunkPos::new(1, 2)));
+    }
+
+    #[test]
+    fn insert_splits_full_leaves() {
+        let mut bvh = ChunkBvh::<ChunkPos>::new().with_max_leaf_items(4);
+        let mut built = ChunkBvh::<ChunkPos>::new().with_max_leaf_items(4);
+
+        let mut rng = rand::thread_rng();
+        let positions: Vec<_> = (0..3000)
+            .map(|_| ChunkPos::new(rng.gen_range(-200..200), rng.gen_range(-200..200)))
+            .collect();
+
+        for &pos in &positions {
+            bvh.insert(pos);
+        }
+
+        bvh.check_invariants();
+        built.build(positions.iter().copied());
+
+        // Leaves stay as small as `build` makes them: either within the
+        // maximum surface area or within the maximum number of items.
+        for node in &bvh.nodes {
+            if let Node::Leaf { bounds, values } = node {
+                assert!(
+                    bounds.surface_area() <= bvh.max_surface_area as i64
+                        || values.len() <= bvh.max_leaf_items,
+                    "leaf with {} values and surface area {}",
+                    values.len(),
+                    bounds.surface_area()
+                );
+            }
+        }
+
+        // Splitting leaves in place keeps the tree about as compact as
+        // building it at once.
+        assert!(bvh.nodes.len() <= built.nodes.len() * 2);
+    }
+
+    #[test]
+    fn insert_with_small_max_surface_area() {
+        let mut bvh = ChunkBvh::<ChunkPos>::new().with_max_surface_area(1);
+        bvh.build([ChunkPos::new(0, 0), ChunkPos::new(1, 0)]);
+
+        // Adjacent and coincident chunks around the origin, including negative
+        // coordinates where the midpoint rounds differently.
+        for x in -3..=3 {
+            for z in -1..=1 {
+                bvh.insert(ChunkPos::new(x, z));
+                bvh.insert(ChunkPos::new(x, z));
+                bvh.check_invariants();
+            }
+        }
+
+        assert_eq!(bvh.len(), 2 + 7 * 3 * 2);
+
+        let mut found = 0;
+        bvh.query(ChunkView::new(ChunkPos::new(0, 0), 8), |_| found += 1);
+        assert_eq!(found, bvh.len());
+    }
+
+    #[test]
+    fn sequential_inserts_stay_shallow() {
+        let mut bvh = ChunkBvh::<ChunkPos>::new().with_max_leaf_items(4);
+
+        // Each new value grows the same leaf at the end of the line, so
+        // splitting in place alone would deepen the tree on every split.
+        for x in 0..5000 {
+            bvh.insert(ChunkPos::new(x, 0));
+        }
+
+        bvh.check_invariants();
+
+        let mut depths: Vec<usize> = vec![];
+        for node in &bvh.nodes {
+            let depth = match node {
+                Node::Internal { left, right, .. } => {
+                    depths[*left as usize].max(depths[*right as usize]) + 1
+                }
+                Node::Leaf { .. } => 1,
+            };
+            depths.push(depth);
+        }
+
+        assert!(depths.last().copied().unwrap_or(0) <= MAX_DEPTH);
+    }
+
+    #[test]
+    fn query_frustum_view_cone() {
+        let mut bvh = ChunkBvh::<ChunkPos>::new();
+        bvh.build((-20..20).flat_map(|x| (-20..20).map(move |z| ChunkPos::new(x, z))));
+
+        // A 90 degree cone facing +Z.
+        let planes = ChunkPlane::view_cone(ChunkPos::new(0, 0), 0.0, 90.0);
+        let inside = |pos: ChunkPos| planes.iter().all(|plane| plane.contains(pos));
+
+        assert!(inside(ChunkPos::new(0, 0)));
+        assert!(inside(ChunkPos::new(0, 5)));
+        assert!(inside(ChunkPos::new(-4, 5)));
+        assert!(inside(ChunkPos::new(4, 5)));
+        assert!(!inside(ChunkPos::new(0, -5)));
+        assert!(!inside(ChunkPos::new(6, 5)));
+        assert!(!inside(ChunkPos::new(-6, 5)));
+
+        let mut found = vec![];
+        bvh.query_frustum(&planes, |pos| found.push(*pos));
+        found.sort_by_key(|pos| (pos.x, pos.z));
+
+        let mut expected: Vec<_> = bvh.iter().copied().filter(|&pos| inside(pos)).collect();
+        expected.sort_by_key(|pos| (pos.x, pos.z));
+
+        assert_eq!(found, expected);
+
+        // Facing -X instead.
+        let planes = ChunkPlane::view_cone(ChunkPos::new(0, 0), 90.0, 90.0);
+        bvh.query_frustum(&planes, |pos| {
+            assert!(pos.x <= 0, "{pos:?} is behind the cone");
+        });
+    }
+
+    #[test]
+    fn query_ring_matches_view_difference() {
+        let mut bvh = ChunkBvh::<ChunkPos>::new();
+        bvh.build((-30..30).flat_map(|x| (-30..30).map(move |z| ChunkPos::new(x, z))));
+
+        let center = ChunkPos::new(3, -2);
+
+        for (inner, outer) in [(0, 0), (2, 8), (5, 6), (8, 2), (0, 32)] {
+            let mut found = vec![];
+            bvh.query_ring(center, inner, outer, |pos| found.push(*pos));
+            found.sort_by_key(|pos| (pos.x, pos.z));
+
+            let inner_view = ChunkView::new(center, inner);
+            let mut expected = vec![];
+            bvh.query(ChunkView::new(center, outer), |pos| {
+                if !inner_view.contains(*pos) {
+                    expected.push(*pos);
+                }
+            });
+            expected.sort_by_key(|pos| (pos.x, pos.z));
+
+            assert_eq!(found, expected, "inner = {inner}, outer = {outer}");
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_round_trip() {
+        #[derive(Copy, Clone, PartialEq, Debug, serde::Serialize, serde::Deserialize)]
+        struct Value(i32, i32);
+
+        impl GetChunkPos for Value {
+            fn chunk_pos(&self) -> ChunkPos {
+                ChunkPos::new(self.0, self.1)
+            }
+        }
+
+        let mut bvh = ChunkBvh::<Value>::new().with_max_surface_area(16);
+        bvh.build((-20..20).flat_map(|x| (-20..20).map(move |z| Value(x * 3, z))));
+
+        let json = serde_json::to_string(&bvh).unwrap();
+        let de: ChunkBvh<Value> = serde_json::from_str(&json).unwrap();
+
+        de.check_invariants();
+        assert_eq!(de.max_surface_area, 16);
+
+        for view in [
+            ChunkView::new(ChunkPos::new(0, 0), 4),
+            ChunkView::new(ChunkPos::new(-30, 7), 10),
+            ChunkView::new(ChunkPos::new(100, 100), 2),
+        ] {
+            let mut expected = vec![];
+            bvh.query(view, |val| expected.push(*val));
+
+            let mut found = vec![];
+            de.query(view, |val| found.push(*val));
+
+            assert_eq!(found, expected);
+        }
+
+        // Moving a value out of its leaf's bounds is caught.
+        let mut value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        value["values"][0] = serde_json::json!([1000, 1000]);
+        assert!(serde_json::from_value::<ChunkBvh<Value>>(value).is_err());
+
+        // So is a child index that is out of bounds.
+        let mut value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        let root = value["nodes"].as_array().unwrap().len() - 1;
+        value["nodes"][root]["Internal"]["left"] = serde_json::json!(u32::MAX);
+        assert!(serde_json::from_value::<ChunkBvh<Value>>(value).is_err());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_rejects_malformed_trees() {
+        #[derive(serde::Serialize, serde::Deserialize)]
+        struct Value(i32, i32);
+
+        impl GetChunkPos for Value {
+            fn chunk_pos(&self) -> ChunkPos {
+                ChunkPos::new(self.0, self.1)
+            }
+        }
+
+        let json = serde_json::to_string(&shared_child_dag(Value(0, 0), 64)).unwrap();
+        assert!(serde_json::from_str::<ChunkBvh<Value>>(&json).is_err());
+
+        let json = serde_json::to_string(&deep_chain(100_000, |i| Value(i, 0))).unwrap();
+        assert!(serde_json::from_str::<ChunkBvh<Value>>(&json).is_err());
+    }
+
+    #[test]
+    fn query_mut_updates_values_in_view() {
+        #[derive(Copy, Clone, PartialEq, Eq, Debug)]
+        struct Value {
+            pos: ChunkPos,
+            hits: u32,
+        }
+
+        impl GetChunkPos for Value {
+            fn chunk_pos(&self) -> ChunkPos {
+                self.pos
+            }
+        }
+
+        let mut bvh = ChunkBvh::<Value>::new();
+        bvh.build((-20..20).flat_map(|x| {
+            (-20..20).map(move |z| Value {
+                pos: ChunkPos::new(x, z),
+                hits: 0,
+            })
+        }));
+
+        let view = ChunkView::new(ChunkPos::new(3, -5), 6);
+
+        let mut visited = 0;
+        bvh.query_mut(view, |val| {
+            val.hits += 1;
+            visited += 1;
+        });
+
+        assert!(visited > 0);
+        bvh.check_invariants();
+
+        let mut in_view = 0;
+        bvh.query(view, |val| {
+            assert_eq!(val.hits, 1);
+            in_view += 1;
+        });
+        assert_eq!(in_view, visited);
+
+        for val in bvh.iter() {
+            assert_eq!(val.hits, u32::from(view.contains(val.pos)));
+        }
+    }
+
+    #[test]
+    fn clear_keeps_capacity() {
+        let mut bvh = ChunkBvh::<ChunkPos>::new();
+        bvh.reserve(1000);
+        assert!(bvh.values.capacity() >= 1000);
+
+        bvh.build((0..30).flat_map(|x| (0..30).map(move |z| ChunkPos::new(x, z))));
+
+        let values_capacity = bvh.values.capacity();
+        let nodes_capacity = bvh.nodes.capacity();
+
+        bvh.clear();
+
+        assert!(bvh.is_empty());
+        assert!(bvh.nodes.is_empty());
+        assert_eq!(bvh.values.capacity(), values_capacity);
+        assert_eq!(bvh.nodes.capacity(), nodes_capacity);
+
+        let mut found = 0;
+        bvh.query(ChunkView::new(ChunkPos::new(0, 0), 32), |_| found += 1);
+        assert_eq!(found, 0);
+
+        // Building again reuses the memory.
+        bvh.build((0..30).flat_map(|x| (0..30).map(move |z| ChunkPos::new(x, z))));
+
+        assert_eq!(bvh.values.capacity(), values_capacity);
+        assert_eq!(bvh.nodes.capacity(), nodes_capacity);
+        bvh.check_invariants();
+    }
+
+    #[test]
+    fn count_matches_query() {
+        let mut rng = rand::thread_rng();
+
+        let mut bvh = ChunkBvh::<ChunkPos>::new();
+        bvh.build(
+            (0..2000).map(|_| ChunkPos::new(rng.gen_range(-100..100), rng.gen_range(-100..100))),
+        );
+
+        for _ in 0..100 {
+            let view = ChunkView::new(
+                ChunkPos::new(rng.gen_range(-120..120), rng.gen_range(-120..120)),
+                rng.gen_range(0..32),
+            );
+
+            let mut expected = 0;
+            bvh.query(view, |_| expected += 1);
+
+            assert_eq!(bvh.count(view), expected);
+        }
+
+        assert_eq!(
+            ChunkBvh::<ChunkPos>::new().count(ChunkView::new(ChunkPos::new(0, 0), 8)),
+            0
+        );
+    }
+
+    #[test]
+    fn max_surface_area_at_runtime() {
+        let positions: Vec<_> = (0..64)
+            .flat_map(|x| (0..64).map(move |z| ChunkPos::new(x, z)))
+            .collect();
+
+        let mut fine = ChunkBvh::<ChunkPos>::new().with_max_surface_area(8);
+        fine.build(positions.iter().copied());
+        fine.check_invariants();
+
+        let mut coarse = ChunkBvh::<ChunkPos>::new().with_max_surface_area(64);
+        coarse.build(positions.iter().copied());
+        coarse.check_invariants();
+
+        // The runtime setting overrides the const generic one.
+        let mut same = ChunkBvh::<ChunkPos, 8>::new();
+        same.build(positions.iter().copied());
+        assert_eq!(same.nodes.len(), fine.nodes.len());
+
+        assert!(fine.nodes.len() > coarse.nodes.len());
+
+        let view = ChunkView::new(ChunkPos::new(30, 30), 6);
+
+        let mut fine_count = 0;
+        let fine_cost = fine.query_counted(view, |_| fine_count += 1);
+
+        let mut coarse_count = 0;
+        let coarse_cost = coarse.query_counted(view, |_| coarse_count += 1);
+
+        // Both find the same values, but the finer BVH visits more, smaller
+        // leaves to do so.
+        assert_eq!(fine_count, coarse_count);
+        assert!(fine_cost.leaves > coarse_cost.leaves);
+        assert!(fine_cost.internal_nodes > coarse_cost.internal_nodes);
+    }
+
+    #[test]
+    fn aabb_contains() {
+        let aabb = ChunkAabb::new(ChunkPos::new(-2, -2), ChunkPos::new(2, 2));
+
+        assert!(aabb.contains(aabb));
+        assert!(aabb.contains(ChunkAabb::point(ChunkPos::new(2, -2))));
+        assert!(aabb.contains(ChunkAabb::new(ChunkPos::new(-1, 0), ChunkPos::new(2, 1))));
+        assert!(!aabb.contains(ChunkAabb::new(ChunkPos::new(-1, 0), ChunkPos::new(3, 1))));
+        assert!(!ChunkAabb::point(ChunkPos::new(0, 0)).contains(aabb));
+    }
+
+    #[test]
+    fn aabb_contains_point_on_boundary() {
+        let aabb = ChunkAabb::new(ChunkPos::new(3, -1), ChunkPos::new(-2, 4));
+
+        // Corners and edges are inside.
+        for pos in [
+            ChunkPos::new(-2, -1),
+            ChunkPos::new(3, 4),
+            ChunkPos::new(-2, 4),
+            ChunkPos::new(3, -1),
+            ChunkPos::new(0, -1),
+            ChunkPos::new(3, 2),
+        ] {
+            assert!(aabb.contains_point(pos), "{pos:?} should be inside");
+        }
+
+        // One past each edge is outside.
+        for pos in [
+            ChunkPos::new(-3, 0),
+            ChunkPos::new(4, 0),
+            ChunkPos::new(0, -2),
+            ChunkPos::new(0, 5),
+            ChunkPos::new(4, 5),
+        ] {
+            assert!(!aabb.contains_point(pos), "{pos:?} should be outside");
+        }
+
+        // A single chunk contains only itself.
+        let point = ChunkAabb::point(ChunkPos::new(i32::MAX, i32::MIN));
+        assert!(point.contains_point(ChunkPos::new(i32::MAX, i32::MIN)));
+        assert!(!point.contains_point(ChunkPos::new(i32::MAX - 1, i32::MIN)));
+        assert!(point.contains(point));
+    }
+
+    #[test]
+    fn aabb_distance_matches_brute_force() {
+        let mut rng = rand::thread_rng();
+
+        for _ in 0..100 {
+            let aabb = ChunkAabb::new(
+                ChunkPos::new(rng.gen_range(-10..10), rng.gen_range(-10..10)),
+                ChunkPos::new(rng.gen_range(-10..10), rng.gen_range(-10..10)),
+            );
+            let pos = ChunkPos::new(rng.gen_range(-20..20), rng.gen_range(-20..20));
+
+            let nearest = (aabb.min.x..=aabb.max.x)
+                .flat_map(|x| (aabb.min.z..=aabb.max.z).map(move |z| ChunkPos::new(x, z)))
+                .map(|chunk| distance_squared(chunk, pos))
+                .min()
+                .unwrap();
+
+            assert_eq!(aabb.distance_squared(pos), nearest);
+            assert_eq!(aabb.distance_squared(pos) == 0, aabb.contains_point(pos));
+        }
+
+        let far = ChunkAabb::point(ChunkPos::new(i32::MAX, i32::MAX));
+        assert_eq!(
+            far.distance_squared(ChunkPos::new(i32::MIN, i32::MIN)),
+            i64::MAX
+        );
+    }
+
+    #[test]
+    fn aabb_intersects_view_matches_contains() {
+        let view = ChunkView::new(ChunkPos::new(3, -2), 4);
+
+        // The last chunk on the +X axis that is still in the view, and the
+        // first one past it.
+        let edge = view.iter().map(|pos| pos.x).max().unwrap();
+        let inside = ChunkAabb::new(ChunkPos::new(edge, -2), ChunkPos::new(edge + 5, -2));
+        let outside = ChunkAabb::new(ChunkPos::new(edge + 1, -2), ChunkPos::new(edge + 5, -2));
+
+        assert!(view.contains(ChunkPos::new(edge, -2)));
+        assert!(!view.contains(ChunkPos::new(edge + 1, -2)));
+        assert!(inside.intersects_view(view));
+        assert!(!outside.intersects_view(view));
+
+        let mut rng = rand::thread_rng();
+
+        for _ in 0..100 {
+            let aabb = ChunkAabb::new(
+                ChunkPos::new(rng.gen_range(-15..15), rng.gen_range(-15..15)),
+                ChunkPos::new(rng.gen_range(-15..15), rng.gen_range(-15..15)),
+            );
+
+            let expected = (aabb.min.x..=aabb.max.x)
+                .flat_map(|x| (aabb.min.z..=aabb.max.z).map(move |z| ChunkPos::new(x, z)))
+                .any(|pos| view.contains(pos));
+
+            assert_eq!(aabb.intersects_view(view), expected);
+        }
+    }
+
+    #[test]
+    fn query_inside_view_fast_path() {
+        let mut bvh = ChunkBvh::<ChunkPos>::new();
+
+        // A dense area, so many leaves are entirely inside the view.
+        let positions: Vec<_> = (-40..40)
+            .flat_map(|x| (-40..40).map(move |z| ChunkPos::new(x, z)))
+            .collect();
+
+        bvh.build(positions.iter().copied());
+
+        for view in [
+            ChunkView::new(ChunkPos::new(0, 0), 20),
+            ChunkView::new(ChunkPos::new(13, -7), 8),
+            ChunkView::new(ChunkPos::new(-40, 40), 32),
+        ] {
+            let mut found = vec![];
+            bvh.query(view, |pos| found.push(*pos));
+            found.sort_by_key(|pos| (pos.x, pos.z));
+
+            let expected: Vec<_> = positions
+                .iter()
+                .copied()
+                .filter(|&pos| view.contains(pos))
+                .collect();
+
+            assert_eq!(found, expected);
+        }
+    }
+
+    #[test]
+    fn max_leaf_items_stops_splitting() {
+        // Two stacks of coincident positions far apart.
+        let positions: Vec<_> = (0..200)
+            .map(|i| ChunkPos::new(if i % 2 == 0 { 0 } else { 1000 }, 0))
+            .collect();
+
+        let mut bvh = ChunkBvh::<ChunkPos>::new();
+        bvh.build(positions.iter().copied());
+
+        assert_eq!(bvh.nodes.len(), 3);
+
+        let mut bvh = ChunkBvh::<ChunkPos>::new().with_max_leaf_items(200);
+        bvh.build(positions.iter().copied());
+
+        // The whole range fits in a single leaf.
+        assert_eq!(bvh.nodes.len(), 1);
+        bvh.check_invariants();
+
+        let mut count = 0;
+        bvh.query(ChunkView::new(ChunkPos::new(0, 0), 4), |_| count += 1);
+        assert_eq!(count, 100);
+    }
+
+    #[test]
+    fn linear_threshold_skips_tree() {
+        let mut rng = rand::thread_rng();
+        let positions: Vec<_> = (0..10)
+            .map(|_| ChunkPos::new(rng.gen_range(-100..100), rng.gen_range(-100..100)))
+            .collect();
+
+        let mut bvh = ChunkBvh::<ChunkPos>::new().with_linear_threshold(10);
+
+        // Just below the threshold, everything is in one leaf.
+        bvh.build(positions[..9].iter().copied());
+        assert_eq!(bvh.nodes.len(), 1);
+        bvh.check_invariants();
+
+        for center in &positions {
+            let view = ChunkView::new(*center, 16);
+
+            let mut found = vec![];
+            bvh.query(view, |pos| found.push(*pos));
+            found.sort_by_key(|pos| (pos.x, pos.z));
+
+            let mut expected: Vec<_> = positions[..9]
+                .iter()
+                .copied()
+                .filter(|&pos| view.contains(pos))
+                .collect();
+            expected.sort_by_key(|pos| (pos.x, pos.z));
+
+            assert_eq!(found, expected);
+        }
+
+        // At the threshold, a tree is built.
+        bvh.build(positions.iter().copied());
+        assert!(bvh.nodes.len() > 1);
+        bvh.check_invariants();
+    }
+
+    #[test]
+    fn query_into_reuses_buffer() {
+        let mut bvh = ChunkBvh::<ChunkPos>::new();
+
+        bvh.build((0..400).map(|i| ChunkPos::new(i % 20, i / 20)));
+
+        let mut buf = Vec::new();
+
+        for view in [
+            ChunkView::new(ChunkPos::new(5, 5), 3),
+            ChunkView::new(ChunkPos::new(15, 12), 6),
+        ] {
+            bvh.query_into(view, &mut buf);
+
+            let mut expected = vec![];
+            bvh.query(view, |pos| expected.push(*pos));
+
+            assert!(!buf.is_empty());
+            assert!(buf.iter().copied().eq(&expected));
+        }
+    }
+
+    #[test]
+    fn validate_detects_corruption() {
+        let mut bvh = ChunkBvh::<ChunkPos>::new();
+
+        bvh.build((0..100).map(|i| ChunkPos::new(i % 10 * 10, i / 10 * 10)));
+
+        assert_eq!(bvh.validate(), Ok(()));
+
+        // Move a value without rebuilding.
+        *bvh.iter_mut().next().unwrap() = ChunkPos::new(1000, 1000);
+
+        assert!(matches!(bvh.validate(), Err(BvhError::LeafBounds { .. })));
+
+        // Add a value that no leaf contains.
+        bvh.build((0..100).map(|i| ChunkPos::new(i % 10 * 10, i / 10 * 10)));
+        bvh.values.push(ChunkPos::new(0, 0));
+
+        assert_eq!(bvh.validate(), Err(BvhError::UncoveredValues));
+    }
+
+    /// A DAG where both children of every internal node are the node before
+    /// it. Walking it as a tree visits the single leaf `2^levels` times.
+    fn shared_child_dag<T: GetChunkPos>(value: T, levels: usize) -> ChunkBvh<T> {
+        let bounds = ChunkAabb::point(value.chunk_pos());
+
+        let mut bvh = ChunkBvh::new();
+        bvh.values.push(value);
+        bvh.nodes.push(Node::Leaf {
+            bounds,
+            values: 0..1,
+        });
+
+        for idx in 1..=levels {
+            let child = (idx - 1) as NodeIdx;
+            bvh.nodes.push(Node::Internal {
+                bounds,
+                left: child,
+                right: child,
+            });
+        }
+
+        bvh
+    }
+
+    /// A well-formed BVH that is a single chain of internal nodes, each with
+    /// one leaf as its other child.
+    fn deep_chain<T: GetChunkPos>(len: i32, value: impl Fn(i32) -> T) -> ChunkBvh<T> {
+        let mut bvh = ChunkBvh::new();
+        let mut top: Option<usize> = None;
+
+        for i in 0..len {
+            let val = value(i);
+            let leaf_bounds = ChunkAabb::point(val.chunk_pos());
+
+            bvh.values.push(val);
+            bvh.nodes.push(Node::Leaf {
+                bounds: leaf_bounds,
+                values: i as NodeIdx..i as NodeIdx + 1,
+            });
+
+            let leaf = bvh.nodes.len() - 1;
+
+            if let Some(top) = top {
+                bvh.nodes.push(Node::Internal {
+                    bounds: bvh.nodes[top].bounds().union(leaf_bounds),
+                    left: top as NodeIdx,
+                    right: leaf as NodeIdx,
+                });
+            }
+
+            top = Some(bvh.nodes.len() - 1);
+        }
+
+        bvh
+    }
+
+    #[test]
+    fn validate_rejects_malformed_trees() {
+        // Would take 2^64 steps to walk recursively.
+        let bvh = shared_child_dag(ChunkPos::new(0, 0), 64);
+        assert_eq!(bvh.validate(), Err(BvhError::SharedNode { node: 0 }));
+
+        // Would overflow the stack when walked recursively.
+        let bvh = deep_chain(100_000, |i| ChunkPos::new(i, 0));
+        assert!(matches!(bvh.validate(), Err(BvhError::TooDeep { .. })));
+
+        // Shallow chains are fine.
+        let mut bvh = deep_chain(MAX_DEPTH as i32 - 1, |i| ChunkPos::new(i, 0));
+        assert_eq!(bvh.validate(), Ok(()));
+
+        // A node outside the tree is caught.
+        let leaf = bvh.nodes[0].clone();
+        bvh.nodes.insert(0, leaf);
+        for node in &mut bvh.nodes {
+            if let Node::Internal { left, right, .. } = node {
+                *left += 1;
+                *right += 1;
+            }
+        }
+        assert_eq!(bvh.validate(), Err(BvhError::UnreachableNode { node: 0 }));
+    }
+
+    #[test]
+    fn query_budget_stops_early() {
+        let mut bvh = ChunkBvh::<ChunkPos>::new();
+
+        // Every position is inside the view.
+        bvh.build((0..100).map(|i| ChunkPos::new(i % 10, i / 10)));
+
+        let view = ChunkView::new(ChunkPos::new(5, 5), 32);
+
+        let mut count = 0;
+        assert!(!bvh.query_budget(view, 40, |_| count += 1));
+        assert_eq!(count, 40);
+
+        let mut count = 0;
+        assert!(bvh.query_budget(view, 100, |_| count += 1));
+        assert_eq!(count, 100);
     }
 }
@@ -0,0 +1,216 @@
+//! Bounding volume hierarchies over the positions of all entities, for
+//! answering "which entities are near this point" without every plugin
+//! scanning the world.
+
+use bevy_app::prelude::*;
+use bevy_ecs::prelude::*;
+use rustc_hash::FxHashMap;
+use valence_entity::{EntityLayerId, Position};
+use valence_math::DVec3;
+use valence_server_common::Despawned;
+use valence_spatial::bvh::{Bvh, Node};
+use valence_spatial::{Bounded3D, SpatialIndex, WithAabb};
+
+use super::UpdateLayersPreClientSet;
+
+/// Adds the [`EntityBvh`] resource and the system which keeps it up to date.
+///
+/// This plugin is not part of the default plugins since the BVHs are rebuilt
+/// from scratch every tick. The rebuild is parallelized, but is still
+/// `O(n log n)` in the number of entities with a [`Position`] and an
+/// [`EntityLayerId`]. Only add it if something actually queries the BVHs.
+pub struct EntityBvhPlugin;
+
+impl Plugin for EntityBvhPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<EntityBvh>().add_systems(
+            PostUpdate,
+            rebuild_entity_bvh.before(UpdateLayersPreClientSet),
+        );
+    }
+}
+
+/// A bounding volume hierarchy per entity layer, containing the [`Position`]
+/// of every entity on that layer. Queries only walk the BVH of the layer they
+/// ask about, so entities on other layers cost nothing.
+///
+/// The BVHs are rebuilt in [`PostUpdate`], so queries made during [`Update`]
+/// see entity positions as they were at the end of the previous tick.
+/// Entities spawned this tick will not be visible until the next one.
+#[derive(Resource, Default)]
+pub struct EntityBvh {
+    layers: FxHashMap<Entity, Bvh<EntityBvhItem>>,
+}
+
+/// An entity, bounded by the single point of its position.
+type EntityBvhItem = WithAabb<Entity>;
+
+impl EntityBvh {
+    /// Invokes `f` with every entity on `layer` within `radius` blocks of
+    /// `center`, along with the entity's position. Entities are visited in
+    /// an arbitrary order.
+    pub fn query_sphere(
+        &self,
+        layer: Entity,
+        center: DVec3,
+        radius: f64,
+        mut f: impl FnMut(Entity, DVec3),
+    ) {
+        let Some(bvh) = self.layers.get(&layer) else {
+            return;
+        };
+
+        let radius_squared = radius * radius;
+
+        bvh.query(
+            |aabb| distance_squared_to_aabb(&aabb, center) <= radius_squared,
+            |item| {
+                let (pos, _) = bounds(item);
+                if pos.distance_squared(center) <= radius_squared {
+                    f(item.object, pos);
+                }
+
+                None::<()>
+            },
+        );
+    }
+
+    /// Returns the entity on `layer` closest to `pos`, or `None` if the layer
+    /// has no entities.
+    pub fn nearest(&self, layer: Entity, pos: DVec3) -> Option<Entity> {
+        let mut best = None;
+
+        if let Some(root) = self.layers.get(&layer).and_then(|bvh| bvh.traverse()) {
+            nearest_rec(root, pos, &mut best);
+        }
+
+        best.map(|(item, _)| item.object)
+    }
+}
+
+fn nearest_rec<'a>(
+    node: Node<'a, EntityBvhItem>,
+    pos: DVec3,
+    best: &mut Option<(&'a EntityBvhItem, f64)>,
+) {
+    match node {
+        Node::Internal(internal) => {
+            let (_, left, right) = internal.split();
+
+            let left_dist = distance_squared_to_aabb(&left, pos);
+            let right_dist = distance_squared_to_aabb(&right, pos);
+
+            // Explore the closest subtree first so the other one is more likely
+            // to be pruned.
+            let mut children = [(left, left_dist), (right, right_dist)];
+            if right_dist < left_dist {
+                children.swap(0, 1);
+            }
+
+            for (child, dist) in children {
+                if !matches!(best, Some((_, best_dist)) if *best_dist <= dist) {
+                    nearest_rec(child, pos, best);
+                }
+            }
+        }
+        Node::Leaf(item) => {
+            let dist = distance_squared_to_aabb(item, pos);
+
+            if !matches!(best, Some((_, best_dist)) if *best_dist <= dist) {
+                *best = Some((item, dist));
+            }
+        }
+    }
+}
+
+fn rebuild_entity_bvh(
+    mut bvh: ResMut<EntityBvh>,
+    entities: Query<(Entity, &Position, &EntityLayerId), Without<Despawned>>,
+) {
+    let mut layers = FxHashMap::<Entity, Vec<EntityBvhItem>>::default();
+
+    for (entity, pos, layer) in &entities {
+        layers
+            .entry(layer.0)
+            .or_default()
+            .push(WithAabb::from_point(entity, pos.0.to_array()));
+    }
+
+    // Keep the BVHs of layers that still have entities to reuse their
+    // allocations.
+    bvh.layers.retain(|layer, _| layers.contains_key(layer));
+
+    for (layer, items) in layers {
+        bvh.layers.entry(layer).or_default().rebuild(items);
+    }
+}
+
+/// Returns the minimum and maximum corners of `bounded`'s bounding box.
+fn bounds(bounded: &impl Bounded3D) -> (DVec3, DVec3) {
+    let aabb = bounded.aabb();
+
+    (
+        DVec3::new(aabb.min.x, aabb.min.y, aabb.min.z),
+        DVec3::new(aabb.max.x, aabb.max.y, aabb.max.z),
+    )
+}
+
+/// Returns the squared distance from `pos` to the closest point in
+/// `bounded`'s bounding box.
+fn distance_squared_to_aabb(bounded: &impl Bounded3D, pos: DVec3) -> f64 {
+    let (min, max) = bounds(bounded);
+
+    pos.clamp(min, max).distance_squared(pos)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn query_entities_near_point() {
+        let mut app = App::new();
+        app.add_plugins(EntityBvhPlugin);
+
+        let layer = app.world.spawn_empty().id();
+        let other_layer = app.world.spawn_empty().id();
+        let empty_layer = app.world.spawn_empty().id();
+
+        let near = app
+            .world
+            .spawn((Position::new([1.0, 0.0, 0.0]), EntityLayerId(layer)))
+            .id();
+        let far = app
+            .world
+            .spawn((Position::new([100.0, 0.0, 0.0]), EntityLayerId(layer)))
+            .id();
+        // Close by, but on a different layer.
+        let other = app
+            .world
+            .spawn((Position::new([0.5, 0.0, 0.0]), EntityLayerId(other_layer)))
+            .id();
+
+        app.update();
+
+        let bvh = app.world.resource::<EntityBvh>();
+
+        let mut found = vec![];
+        bvh.query_sphere(layer, DVec3::ZERO, 10.0, |entity, _| found.push(entity));
+        assert_eq!(found, [near]);
+
+        assert_eq!(bvh.nearest(layer, DVec3::ZERO), Some(near));
+        assert_eq!(bvh.nearest(layer, DVec3::new(90.0, 0.0, 0.0)), Some(far));
+        assert_eq!(bvh.nearest(empty_layer, DVec3::ZERO), None);
+        assert_eq!(bvh.nearest(other_layer, DVec3::ZERO), Some(other));
+
+        // Layers without entities don't keep a BVH around.
+        app.world.despawn(other);
+        app.update();
+
+        let bvh = app.world.resource::<EntityBvh>();
+
+        assert!(!bvh.layers.contains_key(&other_layer));
+        assert_eq!(bvh.nearest(other_layer, DVec3::ZERO), None);
+        assert_eq!(bvh.nearest(layer, DVec3::ZERO), Some(near));
+    }
+}
@@ -92,10 +92,25 @@ impl<T: Bounded3D + Send + Sync> Bvh<T> {
         }
     }
 
+    /// Returns the number of objects in the BVH.
+    pub fn len(&self) -> usize {
+        self.leaf_nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.leaf_nodes.is_empty()
+    }
+
+    /// Returns an iterator over all objects in the BVH in an arbitrary order.
     pub fn iter(&self) -> impl ExactSizeIterator<Item = &T> + FusedIterator + Clone + '_ {
         self.leaf_nodes.iter()
     }
 
+    /// Returns a mutable iterator over all objects in the BVH in an arbitrary
+    /// order.
+    ///
+    /// The bounds of the internal nodes are not updated, so the BVH must be
+    /// rebuilt if the AABB of any object changes.
     pub fn iter_mut(&mut self) -> impl ExactSizeIterator<Item = &mut T> + FusedIterator + '_ {
         self.leaf_nodes.iter_mut()
     }
@@ -427,3 +442,34 @@ impl<O: Bounded3D + Send + Sync> SpatialIndex for Bvh<O> {
         hit
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::WithAabb;
+
+    #[test]
+    fn iter_yields_every_object() {
+        let mut bvh = Bvh::new();
+        assert!(bvh.is_empty());
+        assert_eq!(bvh.iter().count(), 0);
+
+        bvh.rebuild((0..100).map(|i| {
+            let min = Vec3::new(i as f64, (i % 7) as f64, (i % 13) as f64);
+            WithAabb::new(
+                i,
+                Aabb {
+                    min,
+                    max: min + 1.0,
+                },
+            )
+        }));
+
+        assert_eq!(bvh.len(), 100);
+        assert!(!bvh.is_empty());
+
+        let mut objects: Vec<_> = bvh.iter().map(|o| o.object).collect();
+        objects.sort();
+        assert_eq!(objects, (0..100).collect::<Vec<_>>());
+    }
+}
@@ -105,6 +105,22 @@ impl<O, N> WithAabb<O, N> {
     pub fn new(object: O, aabb: Aabb<N>) -> Self {
         Self { object, aabb }
     }
+
+    /// Creates a `WithAabb` whose bounding box is the single point `[x, y, z]`,
+    /// such as the position of an entity.
+    pub fn from_point(object: O, [x, y, z]: [N; 3]) -> Self
+    where
+        N: Copy,
+    {
+        let point = Vec3::new(x, y, z);
+        Self::new(
+            object,
+            Aabb {
+                min: point,
+                max: point,
+            },
+        )
+    }
 }
 
 impl<O, N: Clone> Bounded3D<N> for WithAabb<O, N> {
@@ -3,8 +3,8 @@
 use rand::seq::SliceRandom;
 use valence::prelude::*;
 use valence_boss_bar::{
-    BossBarBundle, BossBarColor, BossBarDivision, BossBarFlags, BossBarHealth, BossBarStyle,
-    BossBarTitle,
+    BossBarBundle, BossBarColor, BossBarDivision, BossBarFlag, BossBarFlags, BossBarHealth,
+    BossBarStyle, BossBarTitle,
 };
 use valence_server::entity::cow::CowEntityBundle;
 use valence_server::message::ChatMessageEvent;
@@ -51,12 +51,11 @@ fn setup(
     let layer_id = commands.spawn(layer).id();
 
     commands.spawn((
-        BossBarBundle {
-            title: BossBarTitle("Boss Bar".into_text()),
-            health: BossBarHealth(0.5),
-            layer: EntityLayerId(layer_id),
-            ..Default::default()
-        },
+        BossBarBundle::builder()
+            .title("Boss Bar")
+            .health(0.5)
+            .layer(layer_id)
+            .build(),
         CustomBossBar,
     ));
 
@@ -173,41 +172,32 @@ fn listen_messages(
                 }
             }
             "color" => {
-                let mut colors = vec![
-                    BossBarColor::Pink,
-                    BossBarColor::Blue,
-                    BossBarColor::Red,
-                    BossBarColor::Green,
-                    BossBarColor::Yellow,
-                ];
-                colors.retain(|c| *c != boss_bar_style.color);
+                let colors = BossBarColor::all()
+                    .iter()
+                    .copied()
+                    .filter(|c| *c != boss_bar_style.color)
+                    .collect::<Vec<_>>();
 
                 let random_color = colors.choose(&mut rand::thread_rng()).unwrap();
 
                 boss_bar_style.color = *random_color;
             }
             "division" => {
-                let mut divisions = vec![
-                    BossBarDivision::NoDivision,
-                    BossBarDivision::SixNotches,
-                    BossBarDivision::TenNotches,
-                    BossBarDivision::TwelveNotches,
-                    BossBarDivision::TwentyNotches,
-                ];
-                divisions.retain(|d| *d != boss_bar_style.division);
+                let divisions = BossBarDivision::all()
+                    .iter()
+                    .copied()
+                    .filter(|d| *d != boss_bar_style.division)
+                    .collect::<Vec<_>>();
 
                 let random_division = divisions.choose(&mut rand::thread_rng()).unwrap();
 
                 boss_bar_style.division = *random_division;
             }
             "flags" => {
-                let darken_sky: bool = rand::random();
-                let dragon_bar: bool = rand::random();
-                let create_fog: bool = rand::random();
-
-                boss_bar_flags.set_darken_sky(darken_sky);
-                boss_bar_flags.set_dragon_bar(dragon_bar);
-                boss_bar_flags.set_create_fog(create_fog);
+                *boss_bar_flags = BossBarFlag::all()
+                    .iter()
+                    .filter(|_| rand::random())
+                    .fold(BossBarFlags::none(), |flags, &flag| flags.toggled(flag));
             }
             _ => {
                 if let Ok(health) = message.parse::<f32>() {
@@ -1,14 +1,25 @@
+use std::borrow::Cow;
+
+use bevy_app::{App, PostUpdate};
+use bevy_ecs::event::Events;
+use bevy_ecs::prelude::*;
+use bevy_ecs::system::{Command, SystemState};
 use valence_boss_bar::{
-    BossBarBundle, BossBarColor, BossBarDivision, BossBarFlags, BossBarHealth, BossBarStyle,
-    BossBarTitle,
+    AnnounceBossBar, BossBarAction, BossBarBundle, BossBarColor, BossBarCommandsExt,
+    BossBarDistanceProgress, BossBarDivision, BossBarFlags, BossBarFollow, BossBarHealth,
+    BossBarHealthError, BossBarHealthThresholdCrossed, BossBarHidden, BossBarMirror,
+    BossBarOverlay, BossBarPerViewer, BossBarResync, BossBarScroll, BossBarSet, BossBarSnapshot,
+    BossBarStyle, BossBarThresholds, BossBarTitle, BossBarTitleReveal, BossBarViewerAdded,
+    BossBarViewerGrace, BossBarViewerRemoved, BossBarViews,
 };
-use valence_server::client::VisibleEntityLayers;
-use valence_server::entity::EntityLayerId;
-use valence_server::protocol::packets::play::BossBarS2c;
-use valence_server::text::IntoText;
-use valence_server::Despawned;
+use valence_server::client::{ViewDistance, VisibleEntityLayers};
+use valence_server::entity::{EntityLayerId, Position};
+use valence_server::protocol::packets::play::{BossBarS2c, OverlayMessageS2c};
+use valence_server::protocol::Packet;
+use valence_server::text::{Color, IntoText};
+use valence_server::{BlockPos, Despawned, EntityLayer, Server, UniqueId};
 
-use crate::testing::ScenarioSingleClient;
+use crate::testing::{create_mock_client, ScenarioSingleClient};
 use crate::Text;
 
 #[test]
@@ -74,6 +85,27 @@ fn test_title_update() {
     frames.assert_count::<BossBarS2c>(1);
 }
 
+#[test]
+fn test_identical_title_update() {
+    let ScenarioSingleClient {
+        mut app,
+        mut helper,
+        layer,
+        ..
+    } = prepare();
+
+    // Reassign the title that was already sent
+    app.world
+        .entity_mut(layer)
+        .insert(BossBarTitle("Boss Bar".into_text()));
+
+    app.update();
+
+    // Nothing changed, so no packet should be sent
+    let frames = helper.collect_received();
+    frames.assert_count::<BossBarS2c>(0);
+}
+
 #[test]
 fn test_health_update() {
     let ScenarioSingleClient {
@@ -136,6 +168,54 @@ fn test_flags_update() {
     frames.assert_count::<BossBarS2c>(1);
 }
 
+#[test]
+fn test_multiple_parts_update() {
+    let ScenarioSingleClient {
+        mut app,
+        mut helper,
+        layer,
+        ..
+    } = prepare();
+
+    // Update every part on the same tick
+    app.world.entity_mut(layer).insert((
+        BossBarTitle("Random".into_text()),
+        BossBarHealth(0.25),
+        BossBarStyle {
+            color: BossBarColor::Green,
+            division: BossBarDivision::TenNotches,
+        },
+        BossBarFlags::new().with_darken_sky(true),
+    ));
+
+    app.update();
+
+    // The boss bar is sent once in full instead of once per part
+    let frames = helper.collect_received();
+    frames.assert_count::<BossBarS2c>(1);
+    assert_eq!(
+        frames.first::<BossBarS2c>().action,
+        BossBarAction::Add {
+            title: Cow::Owned("Random".into_text()),
+            health: 0.25,
+            color: BossBarColor::Green,
+            division: BossBarDivision::TenNotches,
+            flags: BossBarFlags::new().with_darken_sky(true),
+        }
+    );
+
+    // A single part is still sent on its own
+    app.world.entity_mut(layer).insert(BossBarHealth(0.5));
+    app.update();
+
+    let frames = helper.collect_received();
+    frames.assert_count::<BossBarS2c>(1);
+    assert_eq!(
+        frames.first::<BossBarS2c>().action,
+        BossBarAction::UpdateHealth(0.5)
+    );
+}
+
 #[test]
 fn test_client_layer_change() {
     let ScenarioSingleClient {
@@ -170,6 +250,1679 @@ fn test_client_layer_change() {
     frames.assert_count::<BossBarS2c>(1);
 }
 
+#[test]
+fn test_hidden() {
+    let ScenarioSingleClient {
+        mut app,
+        mut helper,
+        layer,
+        ..
+    } = prepare();
+
+    let boss_bar = app
+        .world
+        .spawn(
+            BossBarBundle {
+                title: BossBarTitle("Hidden".into_text()),
+                layer: EntityLayerId(layer),
+                ..Default::default()
+            }
+            .hidden(),
+        )
+        .id();
+
+    app.update();
+
+    // Nothing is sent while the boss bar is hidden, even when it changes
+    app.world.entity_mut(boss_bar).insert(BossBarHealth(0.1));
+    app.update();
+
+    let frames = helper.collect_received();
+    frames.assert_count::<BossBarS2c>(0);
+
+    // Reveal the boss bar
+    app.world.entity_mut(boss_bar).remove::<BossBarHidden>();
+    app.update();
+
+    // We should receive a boss bar packet with the ADD action
+    let frames = helper.collect_received();
+    frames.assert_count::<BossBarS2c>(1);
+    assert!(matches!(
+        frames.first::<BossBarS2c>().action,
+        BossBarAction::Add { health, .. } if health == 0.1
+    ));
+
+    // Hide it again
+    app.world.entity_mut(boss_bar).insert(BossBarHidden);
+    app.update();
+
+    // We should receive a boss bar packet with the REMOVE action
+    let frames = helper.collect_received();
+    frames.assert_count::<BossBarS2c>(1);
+    assert_eq!(frames.first::<BossBarS2c>().action, BossBarAction::Remove);
+}
+
+#[test]
+fn test_title_changed_while_hidden() {
+    let ScenarioSingleClient {
+        mut app,
+        mut helper,
+        layer,
+        ..
+    } = prepare();
+
+    let set_title = |app: &mut App, title: &'static str| {
+        app.world.get_mut::<BossBarTitle>(layer).unwrap().0 = title.into_text();
+        app.update();
+    };
+
+    set_title(&mut app, "A");
+    helper.clear_received();
+
+    // Change the title while the boss bar is hidden
+    app.world.entity_mut(layer).insert(BossBarHidden);
+    app.update();
+    set_title(&mut app, "B");
+
+    // Revealing it shows the new title
+    app.world.entity_mut(layer).remove::<BossBarHidden>();
+    app.update();
+
+    let frames = helper.collect_received();
+    frames.assert_count::<BossBarS2c>(2);
+
+    let actions: Vec<_> = frames
+        .0
+        .iter()
+        .filter(|frame| frame.id == BossBarS2c::ID)
+        .map(|frame| frame.decode::<BossBarS2c>().unwrap().action)
+        .collect();
+    assert_eq!(actions[0], BossBarAction::Remove);
+    assert!(matches!(
+        &actions[1],
+        BossBarAction::Add { title, .. } if **title == "B".into_text()
+    ));
+
+    // Reverting to the title from before the boss bar was hidden is sent
+    set_title(&mut app, "A");
+
+    let frames = helper.collect_received();
+    frames.assert_count::<BossBarS2c>(1);
+    assert_eq!(
+        frames.first::<BossBarS2c>().action,
+        BossBarAction::UpdateTitle(Cow::Owned("A".into_text()))
+    );
+}
+
+#[test]
+fn test_spawn() {
+    let ScenarioSingleClient {
+        mut app,
+        mut helper,
+        layer,
+        ..
+    } = prepare();
+
+    app.world.spawn(BossBarBundle {
+        title: BossBarTitle("Another Boss Bar".into_text()),
+        layer: EntityLayerId(layer),
+        ..Default::default()
+    });
+
+    app.update();
+
+    // We should receive a single boss bar packet with the ADD action
+    let frames = helper.collect_received();
+    frames.assert_count::<BossBarS2c>(1);
+    assert!(matches!(
+        frames.first::<BossBarS2c>().action,
+        BossBarAction::Add { .. }
+    ));
+}
+
+#[test]
+fn test_announce() {
+    let ScenarioSingleClient {
+        mut app,
+        mut helper,
+        client,
+        ..
+    } = prepare();
+
+    // The client can see more than one layer, but should still be shown the
+    // announcement once.
+    let other_layer = EntityLayer::new(app.world.resource::<Server>());
+    let other_layer = app.world.spawn(other_layer).id();
+
+    app.world
+        .get_mut::<VisibleEntityLayers>(client)
+        .unwrap()
+        .0
+        .insert(other_layer);
+
+    app.update();
+    helper.clear_received();
+
+    AnnounceBossBar {
+        title: "Announcement".into_text(),
+        color: BossBarColor::Yellow,
+        duration: 3,
+    }
+    .apply(&mut app.world);
+
+    app.update();
+
+    // We should receive a single boss bar packet with the ADD action
+    let frames = helper.collect_received();
+    frames.assert_count::<BossBarS2c>(1);
+    assert!(matches!(
+        frames.first::<BossBarS2c>().action,
+        BossBarAction::Add {
+            color: BossBarColor::Yellow,
+            ..
+        }
+    ));
+
+    // The boss bar is shown for exactly three ticks, including the one it was
+    // spawned on.
+    for _ in 0..2 {
+        app.update();
+
+        let frames = helper.collect_received();
+        frames.assert_count::<BossBarS2c>(0);
+    }
+
+    app.update();
+
+    // The boss bar's lifetime ran out, so we should receive a boss bar packet
+    // with the REMOVE action
+    let frames = helper.collect_received();
+    frames.assert_count::<BossBarS2c>(1);
+    assert_eq!(frames.first::<BossBarS2c>().action, BossBarAction::Remove);
+
+    app.update();
+
+    // The announcement's layer is removed from the client and despawned.
+    let frames = helper.collect_received();
+    frames.assert_count::<BossBarS2c>(0);
+
+    let visible_entity_layers = app.world.get::<VisibleEntityLayers>(client).unwrap();
+    assert_eq!(visible_entity_layers.0.len(), 2);
+
+    app.update();
+
+    let mut layers_query = app.world.query::<&EntityLayer>();
+    assert_eq!(layers_query.iter(&app.world).count(), 2);
+}
+
+#[test]
+fn test_announce_with_commands() {
+    let ScenarioSingleClient {
+        mut app,
+        mut helper,
+        ..
+    } = prepare();
+
+    helper.clear_received();
+
+    let mut state = SystemState::<Commands>::new(&mut app.world);
+    state
+        .get_mut(&mut app.world)
+        .announce_boss_bar("Announcement", BossBarColor::Red, 3);
+    state.apply(&mut app.world);
+
+    app.update();
+
+    let frames = helper.collect_received();
+    frames.assert_count::<BossBarS2c>(1);
+    assert!(matches!(
+        frames.first::<BossBarS2c>().action,
+        BossBarAction::Add {
+            color: BossBarColor::Red,
+            ..
+        }
+    ));
+}
+
+#[test]
+fn test_mirror() {
+    let ScenarioSingleClient { mut app, layer, .. } = prepare();
+
+    let source = app
+        .world
+        .spawn(BossBarBundle {
+            health: BossBarHealth(0.5),
+            layer: EntityLayerId(layer),
+            ..Default::default()
+        })
+        .id();
+
+    let mirror = app
+        .world
+        .spawn((
+            BossBarBundle {
+                layer: EntityLayerId(layer),
+                ..Default::default()
+            },
+            BossBarMirror(source),
+        ))
+        .id();
+
+    app.update();
+
+    // The mirror picks up the source's current state
+    assert_eq!(app.world.get::<BossBarHealth>(mirror).unwrap().0, 0.5);
+
+    app.world.entity_mut(source).insert(BossBarHealth(0.25));
+    app.world.entity_mut(source).insert(BossBarStyle {
+        color: BossBarColor::Blue,
+        division: BossBarDivision::SixNotches,
+    });
+
+    app.update();
+
+    assert_eq!(app.world.get::<BossBarHealth>(mirror).unwrap().0, 0.25);
+    assert_eq!(
+        app.world.get::<BossBarStyle>(mirror).unwrap().color,
+        BossBarColor::Blue
+    );
+
+    // Despawning the source leaves the mirror in its last state
+    app.world.entity_mut(source).insert(Despawned);
+
+    app.update();
+
+    assert_eq!(app.world.get::<BossBarHealth>(mirror).unwrap().0, 0.25);
+}
+
+#[test]
+fn test_viewer_events() {
+    let ScenarioSingleClient {
+        mut app, client, ..
+    } = prepare();
+
+    let other_layer = EntityLayer::new(app.world.resource::<Server>());
+    let other_layer = app.world.spawn(other_layer).id();
+
+    let boss_bar = app
+        .world
+        .spawn(BossBarBundle::for_layer(other_layer, "Other"))
+        .id();
+
+    app.update();
+
+    let mut added_reader = app
+        .world
+        .resource::<Events<BossBarViewerAdded>>()
+        .get_reader();
+    let mut removed_reader = app
+        .world
+        .resource::<Events<BossBarViewerRemoved>>()
+        .get_reader();
+
+    let mut read_events = |app: &App| {
+        let added = added_reader
+            .read(app.world.resource::<Events<BossBarViewerAdded>>())
+            .copied()
+            .collect::<Vec<_>>();
+        let removed = removed_reader
+            .read(app.world.resource::<Events<BossBarViewerRemoved>>())
+            .copied()
+            .collect::<Vec<_>>();
+        (added, removed)
+    };
+
+    // The client starts seeing the boss bar
+    app.world
+        .get_mut::<VisibleEntityLayers>(client)
+        .unwrap()
+        .0
+        .insert(other_layer);
+    app.update();
+
+    assert_eq!(
+        read_events(&app),
+        (vec![BossBarViewerAdded { boss_bar, client }], vec![])
+    );
+
+    // The client stops seeing the boss bar
+    app.world
+        .get_mut::<VisibleEntityLayers>(client)
+        .unwrap()
+        .0
+        .remove(&other_layer);
+    app.update();
+
+    assert_eq!(
+        read_events(&app),
+        (vec![], vec![BossBarViewerRemoved { boss_bar, client }])
+    );
+}
+
+#[test]
+fn test_viewer_events_on_spawn_hide_and_despawn() {
+    let ScenarioSingleClient {
+        mut app,
+        client,
+        layer,
+        ..
+    } = prepare();
+
+    let mut added_reader = app
+        .world
+        .resource::<Events<BossBarViewerAdded>>()
+        .get_reader_current();
+    let mut removed_reader = app
+        .world
+        .resource::<Events<BossBarViewerRemoved>>()
+        .get_reader_current();
+
+    let mut read_events = |app: &App| {
+        let added = added_reader
+            .read(app.world.resource::<Events<BossBarViewerAdded>>())
+            .copied()
+            .collect::<Vec<_>>();
+        let removed = removed_reader
+            .read(app.world.resource::<Events<BossBarViewerRemoved>>())
+            .copied()
+            .collect::<Vec<_>>();
+        (added, removed)
+    };
+
+    // Spawning a boss bar adds its viewers
+    let boss_bar = app
+        .world
+        .spawn(BossBarBundle::for_layer(layer, "Spawned"))
+        .id();
+    app.update();
+
+    assert_eq!(
+        read_events(&app),
+        (vec![BossBarViewerAdded { boss_bar, client }], vec![])
+    );
+
+    // Hiding it removes them
+    app.world.entity_mut(boss_bar).insert(BossBarHidden);
+    app.update();
+
+    assert_eq!(
+        read_events(&app),
+        (vec![], vec![BossBarViewerRemoved { boss_bar, client }])
+    );
+
+    // Revealing it adds them again
+    app.world.entity_mut(boss_bar).remove::<BossBarHidden>();
+    app.update();
+
+    assert_eq!(
+        read_events(&app),
+        (vec![BossBarViewerAdded { boss_bar, client }], vec![])
+    );
+
+    // Despawning it removes them
+    app.world.entity_mut(boss_bar).insert(Despawned);
+    app.update();
+
+    assert_eq!(
+        read_events(&app),
+        (vec![], vec![BossBarViewerRemoved { boss_bar, client }])
+    );
+
+    // Despawning the layer of a boss bar removes its viewers
+    let other_layer = EntityLayer::new(app.world.resource::<Server>());
+    let other_layer = app.world.spawn(other_layer).id();
+
+    app.world
+        .get_mut::<VisibleEntityLayers>(client)
+        .unwrap()
+        .0
+        .insert(other_layer);
+
+    let boss_bar = app
+        .world
+        .spawn(BossBarBundle::for_layer(other_layer, "Orphaned"))
+        .id();
+    app.update();
+    read_events(&app);
+
+    app.world.entity_mut(other_layer).insert(Despawned);
+    app.update();
+
+    assert_eq!(
+        read_events(&app),
+        (vec![], vec![BossBarViewerRemoved { boss_bar, client }])
+    );
+
+    // So does removing the layer without `Despawned`
+    let other_layer = EntityLayer::new(app.world.resource::<Server>());
+    let other_layer = app.world.spawn(other_layer).id();
+
+    app.world
+        .get_mut::<VisibleEntityLayers>(client)
+        .unwrap()
+        .0
+        .insert(other_layer);
+
+    let boss_bar = app
+        .world
+        .spawn(BossBarBundle::for_layer(other_layer, "Orphaned"))
+        .id();
+    app.update();
+    read_events(&app);
+
+    app.world.despawn(other_layer);
+    app.update();
+
+    assert_eq!(
+        read_events(&app),
+        (vec![], vec![BossBarViewerRemoved { boss_bar, client }])
+    );
+}
+
+#[test]
+fn test_title_reveal() {
+    let ScenarioSingleClient {
+        mut app,
+        helper: mut client_helper,
+        layer,
+        ..
+    } = prepare();
+
+    app.world
+        .entity_mut(layer)
+        .insert(BossBarTitleReveal::new("Hello".into_text(), 2));
+
+    for expected in ["He", "Hell", "Hello"] {
+        app.update();
+
+        let frames = client_helper.collect_received();
+        frames.assert_count::<BossBarS2c>(1);
+
+        let title = app.world.get::<BossBarTitle>(layer).unwrap();
+        assert_eq!(title.0, expected.into_text());
+    }
+
+    assert!(app.world.get::<BossBarTitleReveal>(layer).is_none());
+
+    app.update();
+
+    // Nothing is sent once the full title is shown
+    let frames = client_helper.collect_received();
+    frames.assert_count::<BossBarS2c>(0);
+}
+
+#[test]
+fn test_title_scroll() {
+    let ScenarioSingleClient {
+        mut app,
+        mut helper,
+        layer,
+        ..
+    } = prepare();
+
+    app.world
+        .entity_mut(layer)
+        .insert(BossBarTitle("".color(Color::RED)))
+        .insert(BossBarScroll::new("héllo wörld ", 5, 2));
+
+    // The window moves every other tick, and a title is only sent when it does.
+    for expected in [
+        Some("héllo"),
+        None,
+        Some("éllo "),
+        None,
+        Some("llo w"),
+        None,
+        Some("lo wö"),
+    ] {
+        app.update();
+
+        let frames = helper.collect_received();
+        let title = app.world.get::<BossBarTitle>(layer).unwrap();
+
+        match expected {
+            Some(expected) => {
+                frames.assert_count::<BossBarS2c>(1);
+                assert_eq!(title.0, expected.color(Color::RED));
+            }
+            None => frames.assert_count::<BossBarS2c>(0),
+        }
+    }
+
+    // The text wraps around.
+    app.world.get_mut::<BossBarScroll>(layer).unwrap().offset = 10;
+    app.update();
+
+    let title = app.world.get::<BossBarTitle>(layer).unwrap();
+    assert_eq!(title.0, "d hél".color(Color::RED));
+}
+
+#[test]
+fn test_title_reveal_keeps_formatting() {
+    let ScenarioSingleClient { mut app, layer, .. } = prepare();
+
+    let full = "ab".color(Color::RED) + "cd".bold();
+
+    app.world
+        .entity_mut(layer)
+        .insert(BossBarTitleReveal::new(full.clone(), 3));
+
+    app.update();
+
+    let title = app.world.get::<BossBarTitle>(layer).unwrap();
+    assert_eq!(title.0, "ab".color(Color::RED) + "c".bold());
+
+    app.update();
+
+    let title = app.world.get::<BossBarTitle>(layer).unwrap();
+    assert_eq!(title.0, full);
+}
+
+#[test]
+fn test_layer_despawned() {
+    let ScenarioSingleClient {
+        mut app,
+        client,
+        mut helper,
+        ..
+    } = prepare();
+
+    let other_layer = EntityLayer::new(app.world.resource::<Server>());
+    let other_layer = app.world.spawn(other_layer).id();
+
+    app.world
+        .get_mut::<VisibleEntityLayers>(client)
+        .unwrap()
+        .0
+        .insert(other_layer);
+
+    let boss_bar = app
+        .world
+        .spawn(BossBarBundle {
+            title: BossBarTitle("Other".into_text()),
+            layer: EntityLayerId(other_layer),
+            ..Default::default()
+        })
+        .id();
+
+    // Another kind of entity on the same layer
+    let other_entity = app
+        .world
+        .spawn((UniqueId::default(), EntityLayerId(other_layer)))
+        .id();
+
+    app.update();
+
+    let frames = helper.collect_received();
+    frames.assert_count::<BossBarS2c>(1);
+
+    // Despawn the layer
+    app.world.entity_mut(other_layer).insert(Despawned);
+
+    app.update();
+
+    // The boss bar is removed from the client and despawned
+    let frames = helper.collect_received();
+    frames.assert_count::<BossBarS2c>(1);
+    assert!(matches!(
+        frames.first::<BossBarS2c>().action,
+        BossBarAction::Remove
+    ));
+
+    assert!(app.world.get_entity(boss_bar).is_none());
+    assert!(app.world.get_entity(other_entity).is_some());
+}
+
+#[test]
+fn test_layer_and_boss_bar_despawned() {
+    let ScenarioSingleClient {
+        mut app,
+        client,
+        mut helper,
+        ..
+    } = prepare();
+
+    let other_layer = EntityLayer::new(app.world.resource::<Server>());
+    let other_layer = app.world.spawn(other_layer).id();
+
+    app.world
+        .get_mut::<VisibleEntityLayers>(client)
+        .unwrap()
+        .0
+        .insert(other_layer);
+
+    let boss_bar = app
+        .world
+        .spawn(BossBarBundle::for_layer(other_layer, "Other"))
+        .id();
+
+    app.update();
+    helper.clear_received();
+
+    app.world.entity_mut(other_layer).insert(Despawned);
+    app.world.entity_mut(boss_bar).insert(Despawned);
+
+    app.update();
+
+    // The boss bar is removed exactly once
+    let frames = helper.collect_received();
+    frames.assert_count::<BossBarS2c>(1);
+    assert!(app.world.get_entity(boss_bar).is_none());
+}
+
+#[test]
+fn test_for_layer() {
+    let ScenarioSingleClient {
+        mut app,
+        mut helper,
+        layer,
+        ..
+    } = prepare();
+
+    let boss_bar = app
+        .world
+        .spawn(BossBarBundle::for_layer(layer, "Layer"))
+        .id();
+
+    app.update();
+
+    // Clients that can see the layer get the boss bar
+    let frames = helper.collect_received();
+    frames.assert_count::<BossBarS2c>(1);
+    assert!(matches!(
+        frames.first::<BossBarS2c>().action,
+        BossBarAction::Add { .. }
+    ));
+
+    assert_eq!(app.world.get::<EntityLayerId>(boss_bar).unwrap().0, layer);
+}
+
+#[test]
+fn test_layer_lost_removes_every_boss_bar() {
+    let ScenarioSingleClient {
+        mut app,
+        client,
+        mut helper,
+        layer,
+    } = prepare();
+
+    let other_id = app
+        .world
+        .spawn(BossBarBundle::for_layer(layer, "Other"))
+        .get::<UniqueId>()
+        .unwrap()
+        .0;
+    let layer_id = app.world.get::<UniqueId>(layer).unwrap().0;
+
+    app.update();
+    helper.clear_received();
+
+    app.world
+        .get_mut::<VisibleEntityLayers>(client)
+        .unwrap()
+        .0
+        .remove(&layer);
+
+    app.update();
+
+    // Every boss bar on the lost layer is removed
+    let frames = helper.collect_received();
+    let mut removed: Vec<_> = frames
+        .0
+        .iter()
+        .filter(|frame| frame.id == BossBarS2c::ID)
+        .map(|frame| {
+            let packet = frame.decode::<BossBarS2c>().unwrap();
+            assert!(matches!(packet.action, BossBarAction::Remove));
+            packet.id
+        })
+        .collect();
+
+    removed.sort();
+
+    let mut expected = vec![layer_id, other_id];
+    expected.sort();
+
+    assert_eq!(removed, expected);
+}
+
+#[test]
+fn test_health_thresholds() {
+    let ScenarioSingleClient { mut app, layer, .. } = prepare();
+
+    let mut reader = app
+        .world
+        .resource::<Events<BossBarHealthThresholdCrossed>>()
+        .get_reader();
+
+    app.world
+        .entity_mut(layer)
+        .insert(BossBarThresholds(vec![0.25]));
+
+    let mut set_health = |app: &mut App, health: f32| {
+        app.world.entity_mut(layer).insert(BossBarHealth(health));
+        app.update();
+
+        let events = app
+            .world
+            .resource::<Events<BossBarHealthThresholdCrossed>>();
+        reader.read(events).copied().collect::<Vec<_>>()
+    };
+
+    // Health starts at 0.5, above the threshold
+    assert_eq!(set_health(&mut app, 0.5), []);
+
+    assert_eq!(
+        set_health(&mut app, 0.2),
+        [BossBarHealthThresholdCrossed {
+            boss_bar: layer,
+            threshold: 0.25,
+            rising: false,
+        }]
+    );
+
+    // Small oscillations around the threshold are ignored
+    assert_eq!(set_health(&mut app, 0.255), []);
+    assert_eq!(set_health(&mut app, 0.245), []);
+
+    assert_eq!(
+        set_health(&mut app, 0.3),
+        [BossBarHealthThresholdCrossed {
+            boss_bar: layer,
+            threshold: 0.25,
+            rising: true,
+        }]
+    );
+
+    assert_eq!(set_health(&mut app, 0.4), []);
+}
+
+#[test]
+fn test_health_thresholds_crossed_together() {
+    let ScenarioSingleClient { mut app, layer, .. } = prepare();
+
+    let mut reader = app
+        .world
+        .resource::<Events<BossBarHealthThresholdCrossed>>()
+        .get_reader();
+
+    app.world
+        .entity_mut(layer)
+        .insert(BossBarThresholds(vec![0.25, 0.75, 0.5]));
+
+    let mut set_health = |app: &mut App, health: f32| {
+        app.world.entity_mut(layer).insert(BossBarHealth(health));
+        app.update();
+
+        let events = app
+            .world
+            .resource::<Events<BossBarHealthThresholdCrossed>>();
+        reader
+            .read(events)
+            .map(|event| (event.threshold, event.rising))
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(set_health(&mut app, 0.9), []);
+
+    // Falling health passes the highest threshold first
+    assert_eq!(
+        set_health(&mut app, 0.1),
+        [(0.75, false), (0.5, false), (0.25, false)]
+    );
+
+    // Rising health passes the lowest threshold first
+    assert_eq!(set_health(&mut app, 0.6), [(0.25, true), (0.5, true)]);
+
+    // Overlapping thresholds each need their own margin to be cleared
+    app.world
+        .entity_mut(layer)
+        .insert(BossBarThresholds(vec![0.5, 0.505]));
+
+    assert_eq!(set_health(&mut app, 0.6), []);
+    assert_eq!(set_health(&mut app, 0.493), [(0.505, false)]);
+    assert_eq!(set_health(&mut app, 0.48), [(0.5, false)]);
+}
+
+#[test]
+fn test_viewer_grace() {
+    let ScenarioSingleClient {
+        mut app,
+        client,
+        mut helper,
+        layer,
+    } = prepare();
+
+    app.world.spawn((
+        BossBarBundle::for_layer(layer, "Arena"),
+        Position::new([0.0, 0.0, 0.0]),
+        BossBarViewerGrace { ticks: 3 },
+    ));
+
+    app.update();
+
+    let frames = helper.collect_received();
+    frames.assert_count::<BossBarS2c>(1);
+
+    let move_client = |app: &mut App, x: f64| {
+        app.world
+            .get_mut::<Position>(client)
+            .unwrap()
+            .set([x, 0.0, 0.0]);
+        app.update();
+    };
+
+    // Leaving and coming back within the grace window removes nothing
+    move_client(&mut app, 1000.0);
+    app.update();
+
+    let frames = helper.collect_received();
+    frames.assert_count::<BossBarS2c>(0);
+
+    move_client(&mut app, 0.0);
+
+    let frames = helper.collect_received();
+    frames.assert_count::<BossBarS2c>(1);
+    assert!(matches!(
+        frames.first::<BossBarS2c>().action,
+        BossBarAction::Add { .. }
+    ));
+
+    // Staying away for longer removes the boss bar once the window ends
+    move_client(&mut app, 1000.0);
+    app.update();
+    app.update();
+
+    let frames = helper.collect_received();
+    frames.assert_count::<BossBarS2c>(0);
+
+    app.update();
+
+    let frames = helper.collect_received();
+    frames.assert_count::<BossBarS2c>(1);
+    assert!(matches!(
+        frames.first::<BossBarS2c>().action,
+        BossBarAction::Remove
+    ));
+}
+
+#[test]
+fn test_distance_progress() {
+    let ScenarioSingleClient { mut app, layer, .. } = prepare();
+
+    let tracked = app.world.spawn(Position::new([0.0, 64.0, 0.0])).id();
+
+    app.world.entity_mut(layer).insert(BossBarDistanceProgress {
+        from: BlockPos::new(0, 64, 0),
+        to: BlockPos::new(100, 64, 0),
+        tracked,
+    });
+
+    app.update();
+
+    assert_eq!(app.world.get::<BossBarHealth>(layer).unwrap().0, 0.0);
+
+    let move_tracked = |app: &mut App, pos: [f64; 3]| {
+        app.world.get_mut::<Position>(tracked).unwrap().set(pos);
+        app.update();
+        app.world.get::<BossBarHealth>(layer).unwrap().0
+    };
+
+    assert_eq!(move_tracked(&mut app, [25.0, 64.0, 0.0]), 0.25);
+    // Only progress along the path counts
+    assert_eq!(move_tracked(&mut app, [50.0, 70.0, 30.0]), 0.5);
+    // Progress is clamped
+    assert_eq!(move_tracked(&mut app, [150.0, 64.0, 0.0]), 1.0);
+    assert_eq!(move_tracked(&mut app, [-10.0, 64.0, 0.0]), 0.0);
+
+    move_tracked(&mut app, [75.0, 64.0, 0.0]);
+
+    // The health stays put once the tracked entity is gone
+    app.world.despawn(tracked);
+    app.update();
+
+    assert_eq!(app.world.get::<BossBarHealth>(layer).unwrap().0, 0.75);
+}
+
+#[test]
+fn test_viewer_counts() {
+    let ScenarioSingleClient { mut app, layer, .. } = prepare();
+
+    // A second client on the same layer
+    let (mut second_client, _second_helper) = create_mock_client("second");
+    second_client.player.layer.0 = layer;
+    second_client.visible_chunk_layer.0 = layer;
+    second_client.visible_entity_layers.0.insert(layer);
+    app.world.spawn(second_client);
+
+    let near = app
+        .world
+        .spawn((
+            BossBarBundle::for_layer(layer, "Near"),
+            Position::new([0.0, 0.0, 0.0]),
+        ))
+        .id();
+    let far = app
+        .world
+        .spawn((
+            BossBarBundle::for_layer(layer, "Far"),
+            Position::new([1000.0, 0.0, 0.0]),
+        ))
+        .id();
+    let hidden = app
+        .world
+        .spawn(BossBarBundle::for_layer(layer, "Hidden").hidden())
+        .id();
+
+    app.update();
+
+    let mut state = SystemState::<BossBarViews>::new(&mut app.world);
+    let views = state.get(&app.world);
+
+    assert_eq!(views.count_viewers(layer), 2);
+    assert_eq!(views.count_viewers(near), 2);
+    assert_eq!(views.count_viewers(far), 0);
+    assert_eq!(views.count_viewers(hidden), 0);
+    assert_eq!(views.total_boss_bar_views(), 4);
+}
+
+#[test]
+fn test_per_viewer_health() {
+    let ScenarioSingleClient {
+        mut app,
+        client,
+        mut helper,
+        layer,
+    } = prepare();
+
+    let (mut second_client, mut second_helper) = create_mock_client("second");
+    second_client.player.layer.0 = layer;
+    second_client.visible_chunk_layer.0 = layer;
+    second_client.visible_entity_layers.0.insert(layer);
+    app.world.spawn(second_client);
+
+    app.update();
+    helper.clear_received();
+    second_helper.clear_received();
+
+    // Override the health for the first client only
+    app.world
+        .entity_mut(layer)
+        .insert(BossBarPerViewer([(client, 0.9)].into_iter().collect()));
+    app.update();
+
+    let frames = helper.collect_received();
+    frames.assert_count::<BossBarS2c>(1);
+    assert_eq!(
+        frames.first::<BossBarS2c>().action,
+        BossBarAction::UpdateHealth(0.9)
+    );
+
+    let frames = second_helper.collect_received();
+    frames.assert_count::<BossBarS2c>(1);
+    assert_eq!(
+        frames.first::<BossBarS2c>().action,
+        BossBarAction::UpdateHealth(0.5)
+    );
+
+    // Changing the shared health doesn't affect the override
+    app.world.entity_mut(layer).insert(BossBarHealth(0.2));
+    app.update();
+
+    let frames = helper.collect_received();
+    assert_eq!(
+        frames.first::<BossBarS2c>().action,
+        BossBarAction::UpdateHealth(0.9)
+    );
+
+    let frames = second_helper.collect_received();
+    assert_eq!(
+        frames.first::<BossBarS2c>().action,
+        BossBarAction::UpdateHealth(0.2)
+    );
+
+    // New boss bars are sent with the override
+    app.world.spawn((
+        BossBarBundle::for_layer(layer, "Raid"),
+        BossBarPerViewer([(client, 0.3)].into_iter().collect()),
+    ));
+    app.update();
+
+    let frames = helper.collect_received();
+    assert!(matches!(
+        frames.first::<BossBarS2c>().action,
+        BossBarAction::Add { health, .. } if health == 0.3
+    ));
+
+    let frames = second_helper.collect_received();
+    assert!(matches!(
+        frames.first::<BossBarS2c>().action,
+        BossBarAction::Add { health, .. } if health == 0.0
+    ));
+}
+
+#[test]
+fn test_boss_bar_without_layer_survives() {
+    let ScenarioSingleClient {
+        mut app,
+        mut helper,
+        ..
+    } = prepare();
+
+    // A boss bar built without a layer, and one whose layer is created later
+    let without_layer = app.world.spawn(BossBarBundle::default()).id();
+
+    let later_layer = app.world.spawn_empty().id();
+    let before_layer = app
+        .world
+        .spawn(BossBarBundle::for_layer(later_layer, "Early"))
+        .id();
+
+    for _ in 0..3 {
+        app.update();
+    }
+
+    assert!(app.world.get_entity(without_layer).is_some());
+    assert!(app.world.get_entity(before_layer).is_some());
+    assert!(app.world.get::<Despawned>(without_layer).is_none());
+    assert!(app.world.get::<Despawned>(before_layer).is_none());
+
+    let entity_layer = EntityLayer::new(app.world.resource::<Server>());
+    app.world.entity_mut(later_layer).insert(entity_layer);
+    app.update();
+
+    assert!(app.world.get_entity(before_layer).is_some());
+
+    let frames = helper.collect_received();
+    frames.assert_count::<BossBarS2c>(0);
+}
+
+#[test]
+fn test_layer_removed_without_despawned() {
+    let ScenarioSingleClient {
+        mut app,
+        client,
+        mut helper,
+        ..
+    } = prepare();
+
+    let other_layer = EntityLayer::new(app.world.resource::<Server>());
+    let other_layer = app.world.spawn(other_layer).id();
+
+    app.world
+        .get_mut::<VisibleEntityLayers>(client)
+        .unwrap()
+        .0
+        .insert(other_layer);
+
+    let boss_bar = app
+        .world
+        .spawn(BossBarBundle::for_layer(other_layer, "Other"))
+        .id();
+
+    app.update();
+    helper.clear_received();
+
+    // The layer disappears without going through `Despawned`
+    app.world.despawn(other_layer);
+
+    app.update();
+
+    let frames = helper.collect_received();
+    frames.assert_count::<BossBarS2c>(1);
+    assert!(matches!(
+        frames.first::<BossBarS2c>().action,
+        BossBarAction::Remove
+    ));
+
+    assert!(app.world.get_entity(boss_bar).is_none());
+}
+
+#[test]
+fn test_chunk_view() {
+    let ScenarioSingleClient {
+        mut app,
+        client,
+        mut helper,
+        layer,
+    } = prepare();
+
+    app.world.spawn((
+        BossBarBundle::for_layer(layer, "Positioned"),
+        Position::new([0.0, 0.0, 0.0]),
+    ));
+
+    app.update();
+    helper.clear_received();
+
+    // Returns the actions of the boss bar packets received this tick
+    let mut tick = |app: &mut App| {
+        app.update();
+
+        let frames = helper.collect_received();
+        frames
+            .0
+            .iter()
+            .filter(|frame| frame.id == BossBarS2c::ID)
+            .map(|frame| match frame.decode::<BossBarS2c>().unwrap().action {
+                BossBarAction::Add { .. } => "add",
+                BossBarAction::Remove => "remove",
+                _ => "other",
+            })
+            .collect::<Vec<_>>()
+    };
+
+    let walk_to = |app: &mut App, x: f64| {
+        app.world
+            .get_mut::<Position>(client)
+            .unwrap()
+            .set([x, 0.0, 0.0]);
+    };
+
+    // Walking away and back
+    walk_to(&mut app, 40.0);
+    assert_eq!(tick(&mut app), Vec::<&str>::new());
+    walk_to(&mut app, 100.0);
+    assert_eq!(tick(&mut app), ["remove"]);
+    walk_to(&mut app, 200.0);
+    assert_eq!(tick(&mut app), Vec::<&str>::new());
+    walk_to(&mut app, 50.0);
+    assert_eq!(tick(&mut app), ["add"]);
+    walk_to(&mut app, 100.0);
+    assert_eq!(tick(&mut app), ["remove"]);
+
+    // Changing the view distance
+    app.world.get_mut::<ViewDistance>(client).unwrap().set(8);
+    assert_eq!(tick(&mut app), ["add"]);
+    app.world.get_mut::<ViewDistance>(client).unwrap().set(2);
+    assert_eq!(tick(&mut app), ["remove"]);
+
+    // Gaining the layer and walking into view on the same tick
+    app.world
+        .get_mut::<VisibleEntityLayers>(client)
+        .unwrap()
+        .0
+        .clear();
+    assert_eq!(tick(&mut app), ["remove"]);
+
+    app.world
+        .get_mut::<VisibleEntityLayers>(client)
+        .unwrap()
+        .0
+        .insert(layer);
+    walk_to(&mut app, 0.0);
+    assert_eq!(tick(&mut app), ["add", "add"]);
+}
+
+#[test]
+fn test_follow() {
+    let ScenarioSingleClient {
+        mut app,
+        mut helper,
+        layer,
+        ..
+    } = prepare();
+
+    let anchor = app.world.spawn(Position::new([0.0, 0.0, 0.0])).id();
+
+    let boss_bar = app
+        .world
+        .spawn((
+            BossBarBundle::for_layer(layer, "Follower"),
+            Position::default(),
+            BossBarFollow(anchor),
+        ))
+        .id();
+
+    app.update();
+    helper.clear_received();
+
+    // Returns the actions of the boss bar packets received this tick
+    let mut tick = |app: &mut App| {
+        app.update();
+
+        let frames = helper.collect_received();
+        frames
+            .0
+            .iter()
+            .filter(|frame| frame.id == BossBarS2c::ID)
+            .map(|frame| match frame.decode::<BossBarS2c>().unwrap().action {
+                BossBarAction::Add { .. } => "add",
+                BossBarAction::Remove => "remove",
+                _ => "other",
+            })
+            .collect::<Vec<_>>()
+    };
+
+    let move_anchor = |app: &mut App, x: f64| {
+        app.world
+            .get_mut::<Position>(anchor)
+            .unwrap()
+            .set([x, 0.0, 0.0]);
+    };
+
+    // Moving across chunk boundaries while staying in view
+    move_anchor(&mut app, 40.0);
+    assert_eq!(tick(&mut app), Vec::<&str>::new());
+    assert_eq!(
+        app.world.get::<Position>(boss_bar).unwrap().0,
+        [40.0, 0.0, 0.0].into()
+    );
+
+    // Moving out of view and back
+    move_anchor(&mut app, 100.0);
+    assert_eq!(tick(&mut app), ["remove"]);
+    move_anchor(&mut app, 200.0);
+    assert_eq!(tick(&mut app), Vec::<&str>::new());
+    move_anchor(&mut app, 20.0);
+    assert_eq!(tick(&mut app), ["add"]);
+
+    // The boss bar is despawned with the followed entity
+    app.world.entity_mut(anchor).insert(Despawned);
+    assert_eq!(tick(&mut app), ["remove"]);
+}
+
+#[test]
+fn test_follow_chain() {
+    let ScenarioSingleClient {
+        mut app,
+        mut helper,
+        layer,
+        ..
+    } = prepare();
+
+    let anchor = app.world.spawn(Position::new([0.0, 0.0, 0.0])).id();
+
+    let leader = app
+        .world
+        .spawn((
+            BossBarBundle::for_layer(layer, "Leader"),
+            Position::default(),
+            BossBarFollow(anchor),
+        ))
+        .id();
+
+    let follower = app
+        .world
+        .spawn((
+            BossBarBundle::for_layer(layer, "Follower"),
+            Position::default(),
+            BossBarFollow(leader),
+        ))
+        .id();
+
+    app.update();
+    helper.clear_received();
+
+    app.world
+        .get_mut::<Position>(anchor)
+        .unwrap()
+        .set([100.0, 0.0, 0.0]);
+    app.update();
+
+    // Both boss bars reach the anchor in the same tick
+    for boss_bar in [leader, follower] {
+        assert!(app.world.get::<Despawned>(boss_bar).is_none());
+        assert_eq!(
+            app.world.get::<Position>(boss_bar).unwrap().0,
+            [100.0, 0.0, 0.0].into()
+        );
+    }
+
+    let frames = helper.collect_received();
+    frames.assert_count::<BossBarS2c>(2);
+
+    // Boss bars following each other in a cycle stay where they are
+    app.world.entity_mut(leader).insert(BossBarFollow(follower));
+    app.world
+        .get_mut::<Position>(anchor)
+        .unwrap()
+        .set([0.0, 0.0, 0.0]);
+    app.update();
+
+    for boss_bar in [leader, follower] {
+        assert!(app.world.get::<Despawned>(boss_bar).is_none());
+        assert_eq!(
+            app.world.get::<Position>(boss_bar).unwrap().0,
+            [100.0, 0.0, 0.0].into()
+        );
+    }
+}
+
+#[test]
+fn test_overlay() {
+    let ScenarioSingleClient {
+        mut app,
+        client,
+        mut helper,
+        layer,
+    } = prepare();
+
+    app.world
+        .entity_mut(layer)
+        .insert(BossBarOverlay("Objective".into_text()));
+
+    app.update();
+
+    let frames = helper.collect_received();
+    frames.assert_count::<OverlayMessageS2c>(1);
+    assert_eq!(
+        frames.first::<OverlayMessageS2c>().action_bar_text.as_ref(),
+        &"Objective".into_text()
+    );
+
+    app.update();
+
+    // Nothing is sent without a change
+    let frames = helper.collect_received();
+    frames.assert_count::<OverlayMessageS2c>(0);
+
+    // Clients that can't see the boss bar don't get the overlay
+    app.world
+        .get_mut::<VisibleEntityLayers>(client)
+        .unwrap()
+        .0
+        .clear();
+
+    app.update();
+    helper.clear_received();
+
+    app.world
+        .entity_mut(layer)
+        .insert(BossBarOverlay("Hidden".into_text()));
+
+    app.update();
+
+    let frames = helper.collect_received();
+    frames.assert_count::<OverlayMessageS2c>(0);
+}
+
+#[test]
+fn test_overlay_sent_to_new_viewers() {
+    let ScenarioSingleClient {
+        mut app,
+        client,
+        mut helper,
+        layer,
+    } = prepare();
+
+    app.world
+        .entity_mut(layer)
+        .insert(BossBarOverlay("Objective".into_text()));
+
+    app.update();
+    helper.clear_received();
+
+    let mut received_overlays = |app: &mut App| {
+        app.update();
+
+        let frames = helper.collect_received();
+        frames
+            .0
+            .iter()
+            .filter(|frame| frame.id == OverlayMessageS2c::ID)
+            .map(|frame| {
+                frame
+                    .decode::<OverlayMessageS2c>()
+                    .unwrap()
+                    .action_bar_text
+                    .into_owned()
+            })
+            .collect::<Vec<_>>()
+    };
+
+    // Gaining the boss bar's layer
+    app.world
+        .get_mut::<VisibleEntityLayers>(client)
+        .unwrap()
+        .0
+        .remove(&layer);
+    assert_eq!(received_overlays(&mut app), Vec::<Text>::new());
+
+    app.world
+        .get_mut::<VisibleEntityLayers>(client)
+        .unwrap()
+        .0
+        .insert(layer);
+    assert_eq!(received_overlays(&mut app), ["Objective".into_text()]);
+
+    // Revealing the boss bar
+    app.world.entity_mut(layer).insert(BossBarHidden);
+    assert_eq!(received_overlays(&mut app), Vec::<Text>::new());
+
+    app.world.entity_mut(layer).remove::<BossBarHidden>();
+    assert_eq!(received_overlays(&mut app), ["Objective".into_text()]);
+
+    // Walking into view of a boss bar with a position
+    app.world.spawn((
+        BossBarBundle::for_layer(layer, "Positioned"),
+        BossBarOverlay("Nearby".into_text()),
+        Position::new([0.0, 0.0, 0.0]),
+    ));
+    assert_eq!(received_overlays(&mut app), ["Nearby".into_text()]);
+
+    app.world
+        .get_mut::<Position>(client)
+        .unwrap()
+        .set([200.0, 0.0, 0.0]);
+    assert_eq!(received_overlays(&mut app), Vec::<Text>::new());
+
+    app.world
+        .get_mut::<Position>(client)
+        .unwrap()
+        .set([0.0, 0.0, 0.0]);
+    assert_eq!(received_overlays(&mut app), ["Nearby".into_text()]);
+}
+
+#[test]
+fn test_health_percent() {
+    assert_eq!(BossBarHealth::from_percent(0.0).0, 0.0);
+    assert_eq!(BossBarHealth::from_percent(50.0).0, 0.5);
+    assert_eq!(BossBarHealth::from_percent(100.0).0, 1.0);
+
+    // Out of range percentages are clamped.
+    assert_eq!(BossBarHealth::from_percent(-20.0).0, 0.0);
+    assert_eq!(BossBarHealth::from_percent(150.0).0, 1.0);
+    assert_eq!(BossBarHealth::from_percent(f32::NAN).0, 0.0);
+
+    assert_eq!(BossBarHealth(0.25).percent(), 25.0);
+    assert_eq!(BossBarHealth::from_percent(75.0).percent(), 75.0);
+}
+
+#[test]
+fn test_health_set() {
+    let mut health = BossBarHealth(0.5);
+
+    health.set(1.5);
+    assert_eq!(health.0, 1.0);
+
+    health.set(-0.2);
+    assert_eq!(health.0, 0.0);
+
+    // NaN keeps the previous health.
+    health.set(0.3);
+    health.set(f32::NAN);
+    assert_eq!(health.0, 0.3);
+
+    assert_eq!(health.try_set(0.7), Ok(()));
+    assert_eq!(health.0, 0.7);
+    assert_eq!(
+        health.try_set(1.5),
+        Err(BossBarHealthError::OutOfRange(1.5))
+    );
+    assert_eq!(
+        health.try_set(-0.2),
+        Err(BossBarHealthError::OutOfRange(-0.2))
+    );
+    assert_eq!(health.try_set(f32::NAN), Err(BossBarHealthError::NaN));
+    assert_eq!(health.0, 0.7);
+}
+
+#[test]
+fn test_health_sanitized_on_update() {
+    let ScenarioSingleClient {
+        mut app,
+        mut helper,
+        layer,
+        ..
+    } = prepare();
+
+    // Bypass `set` by writing an out of range value to the field
+    app.world.get_mut::<BossBarHealth>(layer).unwrap().0 = 1.5;
+    app.update();
+
+    let frames = helper.collect_received();
+    assert_eq!(
+        frames.first::<BossBarS2c>().action,
+        BossBarAction::UpdateHealth(1.0)
+    );
+
+    app.world.get_mut::<BossBarHealth>(layer).unwrap().0 = f32::NAN;
+    app.update();
+
+    let frames = helper.collect_received();
+    assert_eq!(
+        frames.first::<BossBarS2c>().action,
+        BossBarAction::UpdateHealth(0.0)
+    );
+}
+
+#[test]
+fn test_system_set() {
+    let ScenarioSingleClient {
+        mut app,
+        mut helper,
+        ..
+    } = prepare();
+
+    // Changes made between the sets are sent in the same tick.
+    app.add_systems(
+        PostUpdate,
+        (|mut boss_bars: Query<&mut BossBarHealth>| {
+            for mut health in &mut boss_bars {
+                if health.0 != 0.25 {
+                    health.0 = 0.25;
+                }
+            }
+        })
+        .after(BossBarSet::Update)
+        .before(BossBarSet::Dispatch),
+    );
+
+    app.update();
+
+    let frames = helper.collect_received();
+    frames.assert_count::<BossBarS2c>(1);
+    assert_eq!(
+        frames.first::<BossBarS2c>().action,
+        BossBarAction::UpdateHealth(0.25)
+    );
+}
+
+#[test]
+fn test_resync() {
+    let ScenarioSingleClient {
+        mut app,
+        mut helper,
+        layer,
+        ..
+    } = prepare();
+
+    let last_changed = |app: &App| {
+        let boss_bar = app.world.entity(layer);
+        [
+            boss_bar.get_ref::<BossBarTitle>().unwrap().last_changed(),
+            boss_bar.get_ref::<BossBarHealth>().unwrap().last_changed(),
+            boss_bar.get_ref::<BossBarStyle>().unwrap().last_changed(),
+            boss_bar.get_ref::<BossBarFlags>().unwrap().last_changed(),
+        ]
+    };
+    let before = last_changed(&app);
+
+    app.world.entity_mut(layer).insert(BossBarResync);
+
+    app.update();
+
+    // The whole boss bar is sent again, although no part changed
+    let frames = helper.collect_received();
+    frames.assert_count::<BossBarS2c>(1);
+    assert!(matches!(
+        frames.first::<BossBarS2c>().action,
+        BossBarAction::Add { health, .. } if health == 0.5
+    ));
+
+    // No part was mutated to do so
+    assert_eq!(last_changed(&app), before);
+    assert!(app.world.get::<BossBarResync>(layer).is_none());
+
+    app.update();
+
+    // The marker only resyncs once
+    let frames = helper.collect_received();
+    frames.assert_count::<BossBarS2c>(0);
+}
+
+#[test]
+fn test_snapshot_add_action() {
+    let title = BossBarTitle("Snapshot".into_text());
+    let health = BossBarHealth(1.5);
+    let style = BossBarStyle {
+        color: BossBarColor::Green,
+        division: BossBarDivision::TenNotches,
+    };
+    let flags = BossBarFlags::new().with_create_fog(true);
+
+    let snapshot = BossBarSnapshot::new(&title, &health, &style, &flags);
+
+    // The health is sanitized
+    assert_eq!(
+        snapshot.add_action(),
+        BossBarAction::Add {
+            title: Cow::Owned("Snapshot".into_text()),
+            health: 1.0,
+            color: BossBarColor::Green,
+            division: BossBarDivision::TenNotches,
+            flags: BossBarFlags::new().with_create_fog(true),
+        }
+    );
+
+    assert_eq!(
+        snapshot.with_health(0.25).add_action(),
+        BossBarAction::Add {
+            title: Cow::Owned("Snapshot".into_text()),
+            health: 0.25,
+            color: BossBarColor::Green,
+            division: BossBarDivision::TenNotches,
+            flags: BossBarFlags::new().with_create_fog(true),
+        }
+    );
+}
+
+#[test]
+fn test_style_presets() {
+    let presets = [
+        ("health", BossBarColor::Red, BossBarDivision::TwelveNotches),
+        ("mana", BossBarColor::Blue, BossBarDivision::NoDivision),
+        (
+            "experience",
+            BossBarColor::Green,
+            BossBarDivision::TwentyNotches,
+        ),
+        ("timer", BossBarColor::Yellow, BossBarDivision::TenNotches),
+        ("boss", BossBarColor::Purple, BossBarDivision::SixNotches),
+    ];
+
+    for (name, color, division) in presets {
+        assert_eq!(
+            BossBarStyle::preset(name),
+            Some(BossBarStyle { color, division }),
+            "{name}"
+        );
+    }
+
+    assert_eq!(BossBarStyle::preset("unknown"), None);
+}
+
 fn prepare() -> ScenarioSingleClient {
     let mut s = ScenarioSingleClient::new();
 